@@ -1,7 +1,7 @@
-use crate::{settings::Settings, ui::ChangeSongNameEvent, GameState};
+use crate::{rng::GameRng, settings::Settings, ui::ChangeSongNameEvent, GameState};
 use bevy::{audio::Volume, prelude::*};
 use bevy_lunex::cosmic_text::Change;
-use rand::{rng, Rng};
+use rand::Rng;
 use std::{time::Duration, usize};
 pub struct AudioPlugin;
 
@@ -142,6 +142,7 @@ pub struct ChangeOrderOfPlaying;
 fn change_order(
     mut change_order: EventReader<ChangeOrderOfPlaying>,
     mut music_player: ResMut<MusicPlayer>,
+    mut rng: ResMut<GameRng>,
 ) {
     for _ev in change_order.read() {
         print!("Before: ");
@@ -172,11 +173,11 @@ fn change_order(
             PlayerMode::Shuffle => {
                 let mut len = music_player.order.len();
                 for i in music_player.current_composition + 1..music_player.order.len() {
-                    music_player.order.swap(i, rand::rng().random_range(i..len));
+                    music_player.order.swap(i, rng.audio().random_range(i..len));
                 }
                 len = music_player.current_composition;
                 for i in 0..music_player.current_composition {
-                    music_player.order.swap(i, rand::rng().random_range(i..len));
+                    music_player.order.swap(i, rng.audio().random_range(i..len));
                 }
             }
         }
@@ -194,6 +195,7 @@ fn tick_timer(
     mut play_sfx: EventWriter<PlayMetroSFXEvent>,
     metro_sfx_q: Query<Entity, With<MetroSounds>>,
     mut player: ResMut<MusicPlayer>,
+    mut rng: ResMut<GameRng>,
 ) {
     if metro_sfx_q.iter().len() != 0 {
         return;
@@ -204,7 +206,7 @@ fn tick_timer(
         play_sfx.send(PlayMetroSFXEvent);
         time_to_sfx
             .time_to_play
-            .set_duration(Duration::from_secs(rand::rng().random_range(14..60)));
+            .set_duration(Duration::from_secs(rng.audio().random_range(14..60)));
         time_to_sfx.time_to_play.reset();
         player.sfx_playing = true;
         println!("Timer is over")
@@ -272,6 +274,7 @@ fn play_metro_sfx(
     music_q: Query<Entity, With<Soundtrack>>,
     music_player: Res<MusicPlayer>,
     settings: Res<Settings>,
+    mut rng: ResMut<GameRng>,
 ) {
     for _ev in play_metro_sfx_event.read() {
         if !settings.turn_on_metro_sfx {
@@ -283,7 +286,7 @@ fn play_metro_sfx(
         commands.spawn((
             AudioPlayer::new(
                 music_player.sfx_metro_list
-                    [rand::rng().random_range(0..music_player.sfx_metro_list.len())]
+                    [rng.audio().random_range(0..music_player.sfx_metro_list.len())]
                 .clone(),
             ),
             MetroSounds,
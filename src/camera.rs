@@ -13,6 +13,7 @@ pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, spawn_camera);
+        app.add_systems(OnExit(GameState::InGame), reset_camera);
         app.add_systems(
             Update,
             (move_camera, zoom_camera).run_if(in_state(GameState::InGame)),
@@ -44,6 +45,13 @@ fn spawn_camera(mut commands: Commands) {
     commands.spawn((Camera2d, MainCamera::default(), UiSourceCamera::<0>));
 }
 
+fn reset_camera(mut q_camera: Query<&mut Transform, With<MainCamera>>) {
+    let Ok(mut camera_transform) = q_camera.get_single_mut() else {
+        return;
+    };
+    camera_transform.translation = Vec3::new(0., 0., 0.);
+}
+
 fn move_camera(
     mut q_camera: Query<(&mut Transform, &MainCamera), Without<UiLayoutRoot>>,
     mut q_tutorial: Query<
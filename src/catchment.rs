@@ -0,0 +1,159 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    district::{start_new_districts, DistrictMap},
+    simulation::SimulationSet,
+    station::{Station, StationButton, CATCHMENT_RADII},
+    station_blueprint::StationBlueprint,
+    GameState, DISTRICT_CELL_SIZE,
+};
+
+// сколько пикселей житель проходит за секунду, в тех же секундах считается и маршрут
+const WALK_SPEED: f32 = 20.;
+
+pub struct CatchmentPlugin;
+
+impl Plugin for CatchmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Catchments>();
+        // районы растут в том же наборе, поэтому пересчитываем после них, а не в случайном порядке
+        app.add_systems(
+            Update,
+            update_catchments
+                .after(start_new_districts)
+                .in_set(SimulationSet::City)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+// пока игрок ставит станцию, видно, какие клетки она и соседние станции обслуживают
+pub struct CatchmentPresentationPlugin;
+
+impl Plugin for CatchmentPresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, draw_catchments.run_if(in_state(GameState::InGame)));
+    }
+}
+
+// станция и сколько секунд до неё идти пешком
+type StationWalk = ((i32, i32), f32);
+
+/// Какие клетки районов обслуживает каждая станция и к каким станциям ходят жители района.
+/// Пересчитывается, только когда меняются станции, их радиусы или клетки районов.
+#[derive(Resource, Default)]
+pub struct Catchments {
+    // радиус охвата каждой станции при последнем пересчёте
+    radii: HashMap<(i32, i32), f32>,
+    cells: HashMap<(i32, i32), Vec<(i32, i32)>>,
+    // ближайшие станции первыми
+    by_district: HashMap<usize, Vec<StationWalk>>,
+}
+
+impl Catchments {
+    pub fn cells(&self, station: (i32, i32)) -> &[(i32, i32)] {
+        self.cells.get(&station).map_or(&[], |cells| cells.as_slice())
+    }
+
+    pub fn stations_for(&self, district: usize) -> &[StationWalk] {
+        self.by_district.get(&district).map_or(&[], |stations| stations.as_slice())
+    }
+}
+
+fn cell_center(cell: (i32, i32)) -> Vec2 {
+    Vec2::new(cell.0 as f32, cell.1 as f32) * DISTRICT_CELL_SIZE
+}
+
+// клетки, центр которых в радиусе от станции: район, клетка и расстояние до неё
+fn served_cells(district_map: &DistrictMap, position: (i32, i32), radius: f32) -> Vec<(usize, (i32, i32), f32)> {
+    let station = Vec2::new(position.0 as f32, position.1 as f32);
+    let mut served = vec![];
+    for district in district_map.districts.iter() {
+        for cell in district.cells.iter() {
+            let distance = cell_center(*cell).distance(station);
+            if distance <= radius {
+                served.push((district.id, *cell, distance));
+            }
+        }
+    }
+    served
+}
+
+fn update_catchments(
+    mut catchments: ResMut<Catchments>,
+    district_map: Res<DistrictMap>,
+    q_added: Query<(), Added<Station>>,
+    // кнопка станции меняется и от наведения курсора, поэтому сверяем сам радиус
+    q_changed: Query<(&Station, &StationButton), Changed<StationButton>>,
+    mut removed: RemovedComponents<Station>,
+    q_station: Query<(&Station, &StationButton)>,
+) {
+    let radius_changed = q_changed
+        .iter()
+        .any(|(station, button)| catchments.radii.get(&station.position) != Some(&button.catchment_radius()));
+    let station_removed = removed.read().count() > 0;
+    if !district_map.is_changed() && q_added.is_empty() && !radius_changed && !station_removed {
+        return;
+    }
+
+    let radii: HashMap<(i32, i32), f32> = q_station
+        .iter()
+        .map(|(station, button)| (station.position, button.catchment_radius()))
+        .collect();
+    let mut cells = HashMap::new();
+    let mut by_district: HashMap<usize, Vec<StationWalk>> = HashMap::new();
+    for (position, radius) in radii.iter() {
+        let served = served_cells(&district_map, *position, *radius);
+        for (district, _, distance) in served.iter() {
+            // до станции идут от ближайшей к ней клетки района
+            let walk_secs = distance / WALK_SPEED;
+            let reachable = by_district.entry(*district).or_default();
+            match reachable.iter_mut().find(|(station, _)| station == position) {
+                Some((_, secs)) => *secs = secs.min(walk_secs),
+                None => reachable.push((*position, walk_secs)),
+            }
+        }
+        cells.insert(*position, served.into_iter().map(|(_, cell, _)| cell).collect());
+    }
+    for reachable in by_district.values_mut() {
+        reachable.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    }
+
+    *catchments = Catchments {
+        radii,
+        cells,
+        by_district,
+    };
+}
+
+fn draw_catchments(
+    q_blueprint: Query<(&Transform, &Visibility), With<StationBlueprint>>,
+    q_station: Query<(&Transform, &Station, &StationButton)>,
+    catchments: Res<Catchments>,
+    district_map: Res<DistrictMap>,
+    mut gizmos: Gizmos,
+) {
+    let Ok((blueprint_transform, visibility)) = q_blueprint.get_single() else {
+        return;
+    };
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+
+    let cell_size = Vec2::splat(DISTRICT_CELL_SIZE * 0.8);
+    for (transform, station, button) in q_station.iter() {
+        let color = Color::WHITE.with_alpha(0.25);
+        gizmos.circle_2d(Isometry2d::from_translation(transform.translation.truncate()), button.catchment_radius(), color);
+        for cell in catchments.cells(station.position) {
+            gizmos.rect_2d(Isometry2d::from_translation(cell_center(*cell)), cell_size, color);
+        }
+    }
+
+    // новая станция строится с базовым радиусом; клетки, которые никто не обслуживает, видны сразу
+    let position = blueprint_transform.translation.truncate();
+    let color = Color::srgb(1.0, 0.85, 0.2);
+    gizmos.circle_2d(Isometry2d::from_translation(position), CATCHMENT_RADII[0], color);
+    for (_, cell, _) in served_cells(&district_map, (position.x as i32, position.y as i32), CATCHMENT_RADII[0]) {
+        gizmos.rect_2d(Isometry2d::from_translation(cell_center(cell)), cell_size, color);
+    }
+}
@@ -0,0 +1,279 @@
+use std::fs;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock,
+    district::{DistrictMap, DistrictType},
+    metro::Metro,
+    money::Money,
+    passenger::{Journey, PassengerDatabase, SendPassengersEvent},
+    rng::GameRng,
+    simulation::SimulationSet,
+    station::{Station, StationButton},
+    train::Train,
+    ui::MoneyRedrawEvent,
+    GameState,
+};
+
+// шанс, что за игровой час в городе что-нибудь случится
+const EVENT_CHANCE_PER_HOUR: f64 = 0.2;
+const CITY_EVENTS_FILE: &str = "assets/city_events.ron";
+
+pub struct CityEventsPlugin;
+
+impl Plugin for CityEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FireCityEvent>()
+            .add_event::<CityEventNotification>()
+            .add_event::<SendPassengersEvent>()
+            .add_event::<MoneyRedrawEvent>();
+        app.init_resource::<CityEvents>();
+        app.add_systems(Startup, load_city_events);
+        app.add_systems(
+            Update,
+            (roll_city_event, apply_city_event, end_out_of_service)
+                .chain()
+                .in_set(SimulationSet::City)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum CityEventEffect {
+    // случайный поезд встаёт на `duration_minutes`
+    TrainBreakdown,
+    // случайная станция закрывается на `duration_minutes`, поезда проезжают её без остановки
+    StationClosure,
+    // жители разом едут в случайный район этого типа
+    PassengerBurst {
+        district_type: DistrictType,
+        count: usize,
+    },
+    Grant {
+        amount: i64,
+    },
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CityEventDefinition {
+    pub message: String,
+    pub effect: CityEventEffect,
+    #[serde(default)]
+    pub duration_minutes: f64,
+    // чем больше, тем чаще случается
+    pub weight: u32,
+}
+
+/// Все городские события. Читаются из `CITY_EVENTS_FILE`, чтобы добавить новое событие
+/// или поменять вероятность, пересобирать игру не нужно.
+#[derive(Resource, Default)]
+pub struct CityEvents(pub Vec<CityEventDefinition>);
+
+/// Поезд или станция временно не работает. Снимается, когда игровое время дойдёт до `until_minutes`.
+#[derive(Component, Clone, Copy, Serialize, Deserialize)]
+pub struct OutOfService {
+    pub until_minutes: f64,
+}
+
+#[derive(Event)]
+struct FireCityEvent {
+    index: usize,
+}
+
+#[derive(Event)]
+pub struct CityEventNotification {
+    pub message: String,
+}
+
+// на что могут подействовать события
+#[derive(SystemParam)]
+struct CityEventTargets<'w, 's> {
+    metro: Res<'w, Metro>,
+    district_map: Res<'w, DistrictMap>,
+    money: ResMut<'w, Money>,
+    q_train: Query<'w, 's, (Entity, &'static Train), Without<OutOfService>>,
+    q_station: Query<'w, 's, (Entity, &'static Station, &'static mut StationButton), Without<OutOfService>>,
+    database: ResMut<'w, PassengerDatabase>,
+    ev_send_passengers: EventWriter<'w, SendPassengersEvent>,
+    ev_redraw_money: EventWriter<'w, MoneyRedrawEvent>,
+}
+
+impl CityEventTargets<'_, '_> {
+    // на закрытую станцию поезда не придут: ждущие и пересаживающиеся там отменяют поездку, как при сносе
+    fn evacuate(&mut self, e_station: Entity) {
+        let Ok((_, station, mut button)) = self.q_station.get_mut(e_station) else {
+            return;
+        };
+        let transferring = Journey::Transferring {
+            station: station.position,
+        };
+        let mut evacuated: Vec<usize> = button.passenger_ids.drain(..).collect();
+        evacuated.extend(
+            self.database
+                .0
+                .iter()
+                .filter(|(_, passenger)| passenger.journey() == transferring)
+                .map(|(id, _)| *id),
+        );
+        evacuated.sort();
+
+        for id in evacuated {
+            if let Some(passenger) = self.database.0.get_mut(&id) {
+                passenger.cancel_trip();
+            }
+        }
+    }
+}
+
+// файла нет или он испорчен - город живёт без событий
+fn load_city_events(mut commands: Commands) {
+    let contents = match fs::read_to_string(CITY_EVENTS_FILE) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("Failed to read {CITY_EVENTS_FILE}: {e}");
+            return;
+        }
+    };
+
+    match ron::from_str::<Vec<CityEventDefinition>>(&contents) {
+        Ok(events) => commands.insert_resource(CityEvents(events)),
+        Err(e) => error!("Corrupted {CITY_EVENTS_FILE}: {e}"),
+    }
+}
+
+fn roll_city_event(
+    clock: Res<GameClock>,
+    events: Res<CityEvents>,
+    mut rng: ResMut<GameRng>,
+    mut last_hour: Local<Option<u32>>,
+    mut ev_fire: EventWriter<FireCityEvent>,
+) {
+    // бросаем кубик раз в игровой час
+    if *last_hour == Some(clock.total_hours()) {
+        return;
+    }
+    let first_roll = last_hour.is_none();
+    *last_hour = Some(clock.total_hours());
+    if first_roll || !rng.random_bool(EVENT_CHANCE_PER_HOUR) {
+        return;
+    }
+
+    let total_weight: u32 = events.0.iter().map(|event| event.weight).sum();
+    if total_weight == 0 {
+        return;
+    }
+    let mut roll = rng.random_range(0..total_weight);
+    for (index, event) in events.0.iter().enumerate() {
+        if roll < event.weight {
+            ev_fire.send(FireCityEvent { index });
+            return;
+        }
+        roll -= event.weight;
+    }
+}
+
+fn apply_city_event(
+    mut commands: Commands,
+    mut ev_fire: EventReader<FireCityEvent>,
+    mut ev_notify: EventWriter<CityEventNotification>,
+    mut targets: CityEventTargets,
+    events: Res<CityEvents>,
+    mut rng: ResMut<GameRng>,
+    clock: Res<GameClock>,
+) {
+    for ev in ev_fire.read() {
+        let event = &events.0[ev.index];
+        let out_of_service = OutOfService {
+            until_minutes: clock.total_minutes() + event.duration_minutes,
+        };
+
+        // события без подходящей цели просто не случаются
+        let target = match event.effect {
+            CityEventEffect::TrainBreakdown => {
+                let trains: Vec<(Entity, usize)> = targets
+                    .q_train
+                    .iter()
+                    .map(|(e_train, train)| (e_train, train.line))
+                    .collect();
+                if trains.is_empty() {
+                    continue;
+                }
+
+                let (e_train, line_id) = trains[rng.random_range(0..trains.len())];
+                commands.entity(e_train).insert(out_of_service);
+                targets.metro.lines[line_id].name.clone()
+            }
+            CityEventEffect::StationClosure => {
+                let stations: Vec<(Entity, String)> = targets
+                    .q_station
+                    .iter()
+                    .map(|(e_station, _, button)| (e_station, button.name.clone()))
+                    .collect();
+                if stations.is_empty() {
+                    continue;
+                }
+
+                let (e_station, name) = stations[rng.random_range(0..stations.len())].clone();
+                commands.entity(e_station).insert(out_of_service);
+                targets.evacuate(e_station);
+                name
+            }
+            CityEventEffect::PassengerBurst { district_type, count } => {
+                let districts: Vec<usize> = targets
+                    .district_map
+                    .districts
+                    .iter()
+                    .filter(|district| district.district_type == district_type)
+                    .map(|district| district.id)
+                    .collect();
+                if districts.is_empty() {
+                    continue;
+                }
+
+                let district_id = districts[rng.random_range(0..districts.len())];
+                targets
+                    .ev_send_passengers
+                    .send(SendPassengersEvent { district_id, count });
+                format!("район {district_id}")
+            }
+            CityEventEffect::Grant { amount } => {
+                targets.money.0 += amount;
+                targets.ev_redraw_money.send(MoneyRedrawEvent);
+                format!("{amount:+}")
+            }
+        };
+
+        let message = format!("{}: {}", event.message, target);
+        info!("{message}");
+        ev_notify.send(CityEventNotification { message });
+    }
+}
+
+fn end_out_of_service(
+    mut commands: Commands,
+    q_out_of_service: Query<(Entity, &OutOfService)>,
+    clock: Res<GameClock>,
+) {
+    for (entity, out_of_service) in q_out_of_service.iter() {
+        if clock.total_minutes() >= out_of_service.until_minutes {
+            commands.entity(entity).remove::<OutOfService>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_event_table_parses() {
+        let events: Vec<CityEventDefinition> =
+            ron::from_str(include_str!("../assets/city_events.ron")).unwrap();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event.weight > 0));
+    }
+}
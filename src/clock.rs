@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{simulation::SimulationSet, GameState};
+
+// игровых минут за секунду реального времени: сутки проходят за 12 минут
+const GAME_MINUTES_PER_SECOND: f32 = 2.;
+const MINUTES_PER_DAY: f64 = 24. * 60.;
+// партия начинается ранним утром, чтобы игрок успел построиться до первого пика
+const START_HOUR: f64 = 6.;
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameClock>();
+        app.add_systems(OnExit(GameState::MainMenu), reset_clock);
+        app.add_systems(
+            Update,
+            tick_clock
+                .in_set(SimulationSet::City)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DayPeriod {
+    Night,
+    MorningPeak,
+    Day,
+    EveningPeak,
+    Evening,
+}
+
+/// Игровое время в минутах с полуночи первого дня. Копится в f64: в f32 за несколько игровых
+/// недель крошечные прибавки за кадр начинают теряться.
+#[derive(Resource, Clone, Copy, Serialize, Deserialize)]
+pub struct GameClock {
+    minutes: f64,
+    #[serde(skip)]
+    delta_minutes: f32,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            minutes: START_HOUR * 60.,
+            delta_minutes: 0.,
+        }
+    }
+}
+
+impl GameClock {
+    // дни считаем с единицы
+    pub fn day(&self) -> u32 {
+        (self.minutes / MINUTES_PER_DAY) as u32 + 1
+    }
+
+    pub fn hour(&self) -> u32 {
+        (self.minutes % MINUTES_PER_DAY / 60.) as u32
+    }
+
+    pub fn minute(&self) -> u32 {
+        (self.minutes % 60.) as u32
+    }
+
+    // полных игровых часов с начала первого дня
+    pub fn total_hours(&self) -> u32 {
+        (self.minutes / 60.) as u32
+    }
+
+    pub fn total_minutes(&self) -> f64 {
+        self.minutes
+    }
+
+    // сколько игровых минут прошло за последний кадр
+    pub fn delta_minutes(&self) -> f32 {
+        self.delta_minutes
+    }
+
+    pub fn period(&self) -> DayPeriod {
+        match self.hour() {
+            7..=9 => DayPeriod::MorningPeak,
+            10..=16 => DayPeriod::Day,
+            17..=19 => DayPeriod::EveningPeak,
+            20..=22 => DayPeriod::Evening,
+            _ => DayPeriod::Night,
+        }
+    }
+
+    pub fn is_rush_hour(&self) -> bool {
+        matches!(self.period(), DayPeriod::MorningPeak | DayPeriod::EveningPeak)
+    }
+}
+
+impl std::fmt::Display for GameClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "День {}, {:02}:{:02}", self.day(), self.hour(), self.minute())
+    }
+}
+
+fn reset_clock(mut commands: Commands) {
+    commands.insert_resource(GameClock::default());
+}
+
+fn tick_clock(mut clock: ResMut<GameClock>, time: Res<Time>) {
+    clock.delta_minutes = time.delta_secs() * GAME_MINUTES_PER_SECOND;
+    clock.minutes += clock.delta_minutes as f64;
+}
@@ -0,0 +1,253 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    cursor::CursorPosition,
+    depot::DEPOT_COST,
+    line::UpdateLineRendererEvent,
+    metro::Metro,
+    money::{refund, Money},
+    passenger::{Journey, Passenger, PassengerDatabase},
+    simulation::SimulationSet,
+    station::{Station, StationButton},
+    train::Train,
+    ui::{MoneyRedrawEvent, PopupMenu},
+    GameState,
+};
+
+// как далеко от центра станции курсор ещё попадает в неё
+const STATION_PICK_RADIUS: f32 = 25.;
+
+pub struct DemolitionPlugin;
+
+impl Plugin for DemolitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DemolishStationEvent>()
+            .add_event::<DemolishLineEvent>()
+            .add_event::<MoneyRedrawEvent>()
+            .add_event::<UpdateLineRendererEvent>();
+        app.add_systems(
+            Update,
+            demolish
+                .in_set(SimulationSet::Demolish)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+// Delete над станцией сносит её, Shift+Delete - одну линию через неё
+pub struct DemolitionPresentationPlugin;
+
+impl Plugin for DemolitionPresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, demolish_hovered.run_if(in_state(GameState::InGame)));
+    }
+}
+
+#[derive(Event)]
+pub struct DemolishStationEvent {
+    pub position: (i32, i32),
+}
+
+#[derive(Event)]
+pub struct DemolishLineEvent {
+    pub line_id: usize,
+}
+
+#[derive(SystemParam)]
+struct Demolition<'w, 's> {
+    commands: Commands<'w, 's>,
+    metro: ResMut<'w, Metro>,
+    money: ResMut<'w, Money>,
+    database: ResMut<'w, PassengerDatabase>,
+    q_station: Query<'w, 's, (Entity, &'static Station, &'static mut StationButton)>,
+    q_train: Query<'w, 's, (Entity, &'static mut Train)>,
+    ev_update_line_renderer: EventWriter<'w, UpdateLineRendererEvent>,
+}
+
+impl Demolition<'_, '_> {
+    // пассажиры, чей маршрут больше не существует, возвращаются в район, откуда вышли
+    fn evacuate(&mut self, affected: impl Fn(&Passenger) -> bool) {
+        let mut evacuated: Vec<usize> = self
+            .database
+            .0
+            .iter()
+            .filter(|(_, passenger)| affected(passenger))
+            .map(|(id, _)| *id)
+            .collect();
+        evacuated.sort();
+
+        for id in evacuated {
+            let passenger = self.database.0.get_mut(&id).unwrap();
+            match passenger.journey() {
+                Journey::Waiting { station } => {
+                    for (_, _, mut station_button) in self
+                        .q_station
+                        .iter_mut()
+                        .filter(|(_, st, _)| st.position == station)
+                    {
+                        station_button.passenger_ids.retain(|passenger_id| *passenger_id != id);
+                    }
+                }
+                Journey::Riding { train } => {
+                    if let Ok((_, mut train)) = self.q_train.get_mut(train) {
+                        train.unload_where(|passenger_id| *passenger_id == id);
+                    }
+                }
+                _ => {}
+            }
+            passenger.cancel_trip();
+        }
+    }
+
+    fn remove_station_entity(&mut self, position: (i32, i32)) {
+        self.metro.stations.remove(position);
+
+        // за расширения станции возвращается та же доля, что и за саму станцию и её платформы
+        let mut spent = 0;
+        if let Some((e_station, _, button)) = self
+            .q_station
+            .iter()
+            .find(|(_, station, _)| station.position == position)
+        {
+            spent += button.built_cost() + button.invested();
+            self.commands.entity(e_station).despawn_recursive();
+        }
+        self.money.0 += refund(spent);
+    }
+
+    fn demolish_station(&mut self, position: (i32, i32)) {
+        let line_ids: Vec<usize> = self.metro.lines_at(position).map(|line| line.id).collect();
+        if line_ids.is_empty() {
+            return;
+        }
+
+        self.evacuate(|passenger| {
+            passenger
+                .route
+                .iter()
+                .any(|step| step.station.position == position)
+        });
+
+        for line_id in line_ids {
+            let line = &mut self.metro.lines[line_id];
+            // депо у сносимой станции сносится вместе с ней
+            if line.depot == Some(position) {
+                self.money.0 += refund(DEPOT_COST);
+            }
+            line.remove_station(position);
+
+            // от линии осталась одна станция - сносим линию целиком
+            if line.stations.len() < 2 {
+                self.demolish_line(line_id);
+                continue;
+            }
+
+            self.metro.update_line_edges(line_id);
+            self.ev_update_line_renderer
+                .send(UpdateLineRendererEvent { line_id });
+        }
+
+        if self.metro.stations.contains(position) {
+            self.remove_station_entity(position);
+        }
+    }
+
+    // линия остаётся в списке пустой, чтобы не сдвигать id остальных
+    fn demolish_line(&mut self, line_id: usize) {
+        self.evacuate(|passenger| passenger.route.iter().any(|step| step.line == line_id));
+
+        for (e_train, train) in self.q_train.iter() {
+            if train.line != line_id {
+                continue;
+            }
+
+            self.commands.entity(e_train).despawn_recursive();
+            self.money.0 += refund(train.value());
+        }
+
+        let positions = self.metro.stations_on(line_id);
+        let line = &mut self.metro.lines[line_id];
+        if line.depot.take().is_some() {
+            self.money.0 += refund(DEPOT_COST);
+        }
+        line.stations.clear();
+        line.is_ring = false;
+        line.update_curve();
+        self.metro.update_line_edges(line_id);
+
+        // пересадочные станции остаются на других линиях
+        for position in positions {
+            if self.metro.stations.degree(position) == 0 {
+                self.remove_station_entity(position);
+            }
+        }
+
+        self.ev_update_line_renderer
+            .send(UpdateLineRendererEvent { line_id });
+    }
+}
+
+fn demolish(
+    mut ev_demolish_station: EventReader<DemolishStationEvent>,
+    mut ev_demolish_line: EventReader<DemolishLineEvent>,
+    mut demolition: Demolition,
+    mut change_money_ui: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_demolish_station.read() {
+        demolition.demolish_station(ev.position);
+        change_money_ui.send(MoneyRedrawEvent);
+    }
+
+    for ev in ev_demolish_line.read() {
+        if demolition.metro.lines[ev.line_id].stations.is_empty() {
+            continue;
+        }
+
+        demolition.demolish_line(ev.line_id);
+        change_money_ui.send(MoneyRedrawEvent);
+    }
+}
+
+fn demolish_hovered(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    cursor_position: Res<CursorPosition>,
+    q_station: Query<&Station>,
+    q_popup: Query<(&PopupMenu, &Visibility)>,
+    metro: Res<Metro>,
+    mut ev_demolish_station: EventWriter<DemolishStationEvent>,
+    mut ev_demolish_line: EventWriter<DemolishLineEvent>,
+) {
+    if !keyboard.just_pressed(KeyCode::Delete) {
+        return;
+    }
+
+    let Some(station) = q_station.iter().find(|station| {
+        Vec2::new(station.position.0 as f32, station.position.1 as f32).distance(cursor_position.0) < STATION_PICK_RADIUS
+    }) else {
+        return;
+    };
+
+    if !keyboard.pressed(KeyCode::ShiftLeft) {
+        ev_demolish_station.send(DemolishStationEvent {
+            position: station.position,
+        });
+        return;
+    }
+
+    // сносим линию, выбранную в меню этой станции, а без меню - ту, для которой станция конечная
+    let picked = q_popup
+        .iter()
+        .find(|(popup, visibility)| **visibility != Visibility::Hidden && popup.station == station.position)
+        .map(|(popup, _)| popup.picked_line)
+        .filter(|line_id| metro.lines_at(station.position).any(|line| line.id == *line_id));
+    let line_id = picked.or_else(|| {
+        metro
+            .lines_at(station.position)
+            .find(|line| line.is_terminal(station.position))
+            .map(|line| line.id)
+    });
+
+    if let Some(line_id) = line_id {
+        ev_demolish_line.send(DemolishLineEvent { line_id });
+    }
+}
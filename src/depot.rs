@@ -0,0 +1,219 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    city_events::{CityEventNotification, OutOfService},
+    clock::GameClock,
+    metro::Metro,
+    money::Money,
+    rng::GameRng,
+    simulation::SimulationSet,
+    train::{Train, TrainOrder},
+    ui::MoneyRedrawEvent,
+    GameState,
+};
+
+pub const DEPOT_COST: i64 = 150;
+// плата за одно обслуживание поезда в депо
+pub const MAINTENANCE_COST: i64 = 15;
+// сколько игровых минут поезд стоит в депо
+pub const MAINTENANCE_MINUTES: f64 = 60.;
+// износ за игровой час работы: без обслуживания поезд изнашивается полностью за 16 часов
+const WEAR_PER_HOUR: f32 = 1. / 16.;
+// с такого износа поезд сам едет на обслуживание, если у линии есть депо
+const MAINTENANCE_WEAR: f32 = 0.6;
+// шанс поломки за игровой час: у нового поезда и добавка у полностью изношенного
+const BREAKDOWN_BASE_CHANCE: f64 = 0.005;
+const BREAKDOWN_WEAR_CHANCE: f64 = 0.25;
+const BREAKDOWN_MINUTES: f64 = 90.;
+
+pub struct DepotPlugin;
+
+impl Plugin for DepotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BuildDepotEvent>()
+            .add_event::<CityEventNotification>()
+            .add_event::<MoneyRedrawEvent>();
+        app.add_systems(
+            Update,
+            build_depot
+                .in_set(SimulationSet::Build)
+                .run_if(in_state(GameState::InGame)),
+        );
+        app.add_systems(
+            Update,
+            (wear_trains, schedule_maintenance, roll_breakdowns, leave_depot)
+                .chain()
+                .in_set(SimulationSet::City)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+// депо рисуется прямоугольником рядом со станцией, поезда на обслуживании не видны
+pub struct DepotPresentationPlugin;
+
+impl Plugin for DepotPresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (sync_depot_visuals, hide_trains_in_depot));
+    }
+}
+
+/// Построить депо линии `line_id` у станции `station`. Старое депо линии при этом сносится.
+#[derive(Event)]
+pub struct BuildDepotEvent {
+    pub line_id: usize,
+    pub station: (i32, i32),
+}
+
+/// Поезд стоит в депо на обслуживании.
+#[derive(Component)]
+pub struct InDepot;
+
+fn build_depot(
+    mut ev_build_depot: EventReader<BuildDepotEvent>,
+    mut metro: ResMut<Metro>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_build_depot.read() {
+        let line = &mut metro.lines[ev.line_id];
+        if money.0 < DEPOT_COST
+            || line.depot == Some(ev.station)
+            || !line.stations.iter().any(|station| station.position == ev.station)
+        {
+            continue;
+        }
+
+        line.depot = Some(ev.station);
+        money.0 -= DEPOT_COST;
+        redraw_money.send(MoneyRedrawEvent);
+    }
+}
+
+// поезд изнашивается всё время, пока ходит по линии
+fn wear_trains(mut q_train: Query<&mut Train, Without<OutOfService>>, clock: Res<GameClock>) {
+    for mut train in q_train.iter_mut() {
+        train.wear = (train.wear + clock.delta_minutes() / 60. * WEAR_PER_HOUR).min(1.);
+    }
+}
+
+// изношенные поезда линий с депо сами едут на обслуживание
+fn schedule_maintenance(
+    mut commands: Commands,
+    q_train: Query<(Entity, &Train, Option<&TrainOrder>), Without<OutOfService>>,
+    metro: Res<Metro>,
+) {
+    for (e_train, train, order) in q_train.iter() {
+        let has_depot = metro.lines[train.line].depot.is_some();
+        match order {
+            None if train.wear >= MAINTENANCE_WEAR && has_depot => {
+                commands.entity(e_train).insert(TrainOrder::Maintenance);
+            }
+            // депо снесли, пока поезд ехал к нему
+            Some(TrainOrder::Maintenance) if !has_depot => {
+                commands.entity(e_train).remove::<TrainOrder>();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn roll_breakdowns(
+    mut commands: Commands,
+    q_train: Query<(Entity, &Train), Without<OutOfService>>,
+    metro: Res<Metro>,
+    clock: Res<GameClock>,
+    mut rng: ResMut<GameRng>,
+    mut last_hour: Local<Option<u32>>,
+    mut ev_notify: EventWriter<CityEventNotification>,
+) {
+    // как и городские события, бросаем кубик раз в игровой час
+    if *last_hour == Some(clock.total_hours()) {
+        return;
+    }
+    let first_roll = last_hour.is_none();
+    *last_hour = Some(clock.total_hours());
+    if first_roll {
+        return;
+    }
+
+    for (e_train, train) in q_train.iter() {
+        let wear = train.wear as f64;
+        if !rng.random_bool(BREAKDOWN_BASE_CHANCE + wear * wear * BREAKDOWN_WEAR_CHANCE) {
+            continue;
+        }
+
+        commands.entity(e_train).insert(OutOfService {
+            until_minutes: clock.total_minutes() + BREAKDOWN_MINUTES,
+        });
+        let message = format!("Поломка поезда: {}", metro.lines[train.line].name);
+        info!("{message}");
+        ev_notify.send(CityEventNotification { message });
+    }
+}
+
+// обслуживание закончилось - поезд выходит обратно на линию
+fn leave_depot(mut commands: Commands, q_train: Query<Entity, (With<InDepot>, Without<OutOfService>)>) {
+    for e_train in q_train.iter() {
+        commands.entity(e_train).remove::<InDepot>();
+    }
+}
+
+#[derive(Component)]
+struct DepotVisual {
+    line_id: usize,
+    station: (i32, i32),
+}
+
+// депо стоит чуть ниже своей станции
+const DEPOT_OFFSET: Vec2 = Vec2::new(0., -32.);
+
+fn sync_depot_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    q_depot: Query<(Entity, &DepotVisual)>,
+    metro: Res<Metro>,
+) {
+    // депо снесли вместе со станцией или линией, или перенесли к другой станции
+    for (e_depot, depot) in q_depot.iter() {
+        if metro.lines.get(depot.line_id).and_then(|line| line.depot) != Some(depot.station) {
+            commands.entity(e_depot).despawn_recursive();
+        }
+    }
+
+    for line in metro.lines.iter() {
+        let Some(station) = line.depot else { continue };
+        if q_depot
+            .iter()
+            .any(|(_, depot)| depot.line_id == line.id && depot.station == station)
+        {
+            continue;
+        }
+
+        let position = Vec2::new(station.0 as f32, station.1 as f32) + DEPOT_OFFSET;
+        commands.spawn((
+            StateScoped(GameState::InGame),
+            Mesh2d(meshes.add(Rectangle::new(36., 16.))),
+            MeshMaterial2d(materials.add(line.color.darker(0.2))),
+            Transform::from_translation(position.extend(0.5)),
+            DepotVisual {
+                line_id: line.id,
+                station,
+            },
+        ));
+    }
+}
+
+fn hide_trains_in_depot(mut q_train: Query<(&mut Visibility, Has<InDepot>), With<Train>>) {
+    for (mut visibility, in_depot) in q_train.iter_mut() {
+        let wanted = match in_depot {
+            true => Visibility::Hidden,
+            false => Visibility::Inherited,
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+}
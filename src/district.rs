@@ -1,8 +1,11 @@
 use std::time::Duration;
 
 use bevy::{prelude::*, time::common_conditions::on_timer};
+use serde::{Deserialize, Serialize};
 
-use crate::{metro::Metro, passenger::AddPassengerEvent, GameState, DISTRICT_CELL_SIZE, MAX_DISTRICT_SIZE};
+use rand::Rng;
+
+use crate::{metro::Metro, passenger::AddPassengerEvent, rng::GameRng, save::PendingLoad, simulation::SimulationSet, GameState, DISTRICT_CELL_SIZE, MAX_DISTRICT_SIZE};
 
 pub struct DistrictPlugin;
 
@@ -10,7 +13,7 @@ impl Plugin for DistrictPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<DistrictMap>();
         app.add_systems(OnEnter(GameState::InGame), (
-            test_gen_district,
+            test_gen_district.run_if(not(resource_exists::<PendingLoad>)),
         ));
         app.add_systems(Update, (
             grow_districts
@@ -18,13 +21,22 @@ impl Plugin for DistrictPlugin {
             start_new_districts
                 .run_if(on_timer(Duration::from_millis(1000)))
                 .run_if(should_start_new_districts),
-            draw_district_cells
         )
+        .chain()
+        .in_set(SimulationSet::City)
         .run_if(in_state(GameState::InGame)));
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+pub struct DistrictPresentationPlugin;
+
+impl Plugin for DistrictPresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, draw_district_cells.run_if(in_state(GameState::InGame)));
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DistrictType {
     Home,
     Work,
@@ -46,7 +58,7 @@ pub struct DistrictCell {
     position: (i32, i32)
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub (crate) struct District {
     is_completed: bool,
     derivatives_amount: u32,
@@ -74,7 +86,7 @@ impl Default for District {
     }
 }
 
-#[derive(Resource, Default)]
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
 pub struct DistrictMap {
     pub(crate) districts: Vec<District>,
     cells: Vec<(i32, i32)>,
@@ -110,8 +122,9 @@ fn should_start_new_districts(
     false
 }
 
-fn start_new_districts(
+pub(crate) fn start_new_districts(
     mut district_map: ResMut<DistrictMap>,
+    mut rng: ResMut<GameRng>,
 ) {
     for district in district_map.districts.clone().iter()
         .filter(|&dist| dist.is_completed && dist.derivatives_amount < 4 && dist.is_fertile) {
@@ -121,19 +134,19 @@ fn start_new_districts(
         let random_type: DistrictType;
         match district.district_type {
             DistrictType::Entertainment => {
-                random_type = match rand::random_bool(0.5) {
+                random_type = match rng.random_bool(0.5) {
                     false => DistrictType::Home,
                     true => DistrictType::Work,
                 };
             },
             DistrictType::Home => {
-                random_type = match rand::random_bool(0.5) {
+                random_type = match rng.random_bool(0.5) {
                     false => DistrictType::Entertainment,
                     true => DistrictType::Work,
                 };
             },
             DistrictType::Work => {
-                random_type = match rand::random_bool(0.5) {
+                random_type = match rng.random_bool(0.5) {
                     false => DistrictType::Home,
                     true => DistrictType::Entertainment,
                 };
@@ -167,8 +180,8 @@ fn start_new_districts(
         let new_district = District {
             id: district_map.districts.len(),
             district_type: random_type,
-            cells: vec![border_points[rand::random_range(0..border_points.len())]],
-            is_fertile: rand::random_bool(0.7),
+            cells: vec![border_points[rng.random_range(0..border_points.len())]],
+            is_fertile: rng.random_bool(0.7),
             ..default()
         };
 
@@ -1,9 +1,11 @@
 use std::collections::LinkedList;
 
-use bevy::prelude::*;
-use rand::prelude::*;
+use bevy::{math::cubic_splines::CyclicCubicGenerator, prelude::*};
+use bevy_2d_line::LineRenderingPlugin;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::{metro::Metro, station::Station, GameState};
+use crate::{metro::Metro, simulation::SimulationSet, station::Station, GameState};
 
 pub struct MetroLinePlugin;
 
@@ -11,58 +13,359 @@ impl Plugin for MetroLinePlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnLineCurveEvent>();
         app.add_event::<UpdateLineRendererEvent>();
+        app.add_event::<RenameLineEvent>();
+        app.add_systems(
+            Update,
+            rename_line
+                .in_set(SimulationSet::Build)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+// кривые линий рисуются через bevy_2d_line, симуляции они не нужны
+pub struct MetroLinePresentationPlugin;
+
+impl Plugin for MetroLinePresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(LineRenderingPlugin);
         app.add_systems(Update, (spawn_line_curve, update_line_renderer));
     }
 }
 
-pub const LINE_NAMES: [&str;10] = ["Линия 1","Линия 2","Линия 3","Линия 4","Линия 5","Линия 6","Линия 7","Линия 8","Линия 9","Линия 10"];
+// замкнуть в кольцо можно линию хотя бы из трёх станций
+pub const MIN_RING_STATIONS: usize = 3;
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
 pub struct MetroLine {
     pub name: String,
     pub id: usize,
     pub stations: LinkedList<Station>,
+    #[serde(skip)] // кривая не сохраняется, а перестраивается по станциям после загрузки
     pub curve: Option<CubicCurve<Vec2>>,
     pub color: Color,
+    #[serde(default)] // последняя станция соединена с первой
+    pub is_ring: bool,
+    #[serde(skip)] // как и кривая, перестраивается по станциям
+    pub path: Option<LinePath>,
+    #[serde(default)] // желаемый интервал между поездами в секундах, None - поровну на всю линию
+    pub headway_secs: Option<f32>,
+    #[serde(default)] // здесь останавливаются экспрессы и поезда обеих схем через одну
+    pub express_stops: Vec<(i32, i32)>,
+    #[serde(default)] // станция, у которой стоит депо линии
+    pub depot: Option<(i32, i32)>,
+    #[serde(default)] // какая из схем «через одну» останавливается на станции
+    pub skip_stops: Vec<((i32, i32), ServicePattern)>,
+}
+
+/// По каким станциям линии ходит поезд. Конечные и экспресс-станции линии обслуживают все схемы.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug, Serialize, Deserialize)]
+pub enum ServicePattern {
+    #[default]
+    AllStops,
+    // только экспресс-станции
+    Express,
+    // через одну: каждая станция закреплена за A или за B, соседние обычно за разными
+    SkipA,
+    SkipB,
+}
+
+impl ServicePattern {
+    pub const ALL: [ServicePattern; 4] = [
+        ServicePattern::AllStops,
+        ServicePattern::Express,
+        ServicePattern::SkipA,
+        ServicePattern::SkipB,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServicePattern::AllStops => "Все",
+            ServicePattern::Express => "Экспресс",
+            ServicePattern::SkipA => "Через одну A",
+            ServicePattern::SkipB => "Через одну B",
+        }
+    }
+
+    // следующая схема в списке, по кругу
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|pattern| pattern == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+// сколько точек кривой берём на отрезок между станциями, когда меряем длину
+const SAMPLES_PER_SEGMENT: usize = 32;
+// расстояние между соседними точками пути поезда
+const PATH_STEP: f32 = 2.;
+
+/// Путь поезда вдоль линии, параметризованный длиной дуги: точки лежат на равном расстоянии друг от друга,
+/// поэтому положение поезда по пройденному пути находится без поиска.
+#[derive(PartialEq, Clone)]
+pub struct LinePath {
+    points: Vec<Vec2>,
+    step: f32,
+    length: f32,
+    is_ring: bool,
+    // расстояние от начала пути до каждой станции, в порядке станций линии
+    station_offsets: Vec<f32>,
+}
+
+impl LinePath {
+    fn new(curve: &CubicCurve<Vec2>, is_ring: bool) -> Self {
+        let segments = curve.segments().len();
+        // кривая проходит через станции на границах отрезков, у кольца последняя точка совпадает с первой
+        let station_count = if is_ring { segments } else { segments + 1 };
+
+        let dense: Vec<Vec2> = curve.iter_positions(SAMPLES_PER_SEGMENT * segments).collect();
+        let mut lengths = vec![0.];
+        for pair in dense.windows(2) {
+            lengths.push(lengths[lengths.len() - 1] + pair[0].distance(pair[1]));
+        }
+        let length = lengths[lengths.len() - 1];
+        let station_offsets = (0..station_count)
+            .map(|index| lengths[index * SAMPLES_PER_SEGMENT])
+            .collect();
+
+        // переразбиваем кривую на равные по длине шаги
+        let count = ((length / PATH_STEP).ceil() as usize).max(1);
+        let step = length / count as f32;
+        let mut points = Vec::with_capacity(count + 1);
+        let mut sample = 0;
+        for index in 0..=count {
+            let distance = (index as f32 * step).min(length);
+            while sample + 2 < lengths.len() && lengths[sample + 1] < distance {
+                sample += 1;
+            }
+            let span = lengths[sample + 1] - lengths[sample];
+            let t = if span > 0. { (distance - lengths[sample]) / span } else { 0. };
+            points.push(dense[sample].lerp(dense[sample + 1], t.clamp(0., 1.)));
+        }
+
+        Self {
+            points,
+            step,
+            length,
+            is_ring,
+            station_offsets,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+
+    pub fn station_offsets(&self) -> &[f32] {
+        &self.station_offsets
+    }
+
+    // по кольцу путь продолжается с начала, обычная линия упирается в конечные
+    pub fn wrap(&self, distance: f32) -> f32 {
+        match self.is_ring {
+            true => distance.rem_euclid(self.length),
+            false => distance.clamp(0., self.length),
+        }
+    }
+
+    pub fn position(&self, distance: f32) -> Vec2 {
+        let (index, t) = self.locate(distance);
+        self.points[index].lerp(self.points[index + 1], t)
+    }
+
+    // направление пути в сторону увеличения расстояния
+    pub fn tangent(&self, distance: f32) -> Vec2 {
+        let (index, _) = self.locate(distance);
+        (self.points[index + 1] - self.points[index]).normalize_or_zero()
+    }
+
+    // ближайшая к точке позиция на пути; нужна, когда путь перестроили под поездом
+    pub fn project(&self, point: Vec2) -> f32 {
+        let index = self
+            .points
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.distance(point).total_cmp(&b.distance(point)))
+            .map_or(0, |(index, _)| index);
+        self.wrap(index as f32 * self.step)
+    }
+
+    // отрезок пути, на котором лежит точка, и доля пройденного на нём
+    fn locate(&self, distance: f32) -> (usize, f32) {
+        let position = self.wrap(distance) / self.step;
+        let index = (position.floor() as usize).min(self.points.len() - 2);
+        (index, (position - index as f32).clamp(0., 1.))
+    }
 }
 
 impl MetroLine {
-    fn update_curve(&mut self) { // обновляем точки, по которым строится кривая
-        self.curve = CubicCardinalSpline::new_catmull_rom(self.stations
-            .iter().map(|station| Vec2::new(station.position.0 as f32, station.position.1 as f32)).collect::<Vec<Vec2>>())
-            .to_curve().ok();
+    pub fn update_curve(&mut self) { // обновляем точки, по которым строится кривая
+        let spline = CubicCardinalSpline::new_catmull_rom(self.stations
+            .iter().map(|station| Vec2::new(station.position.0 as f32, station.position.1 as f32)).collect::<Vec<Vec2>>());
+        self.curve = match self.is_ring {
+            true => spline.to_curve_cyclic().ok(),
+            false => spline.to_curve().ok(),
+        };
+        self.path = self.curve.as_ref().map(|curve| LinePath::new(curve, self.is_ring));
+    }
+
+    // к конечной станции можно пристроить продолжение, у кольца конечных нет
+    pub fn is_terminal(&self, position: (i32, i32)) -> bool {
+        !self.is_ring
+            && (self.stations.front().is_some_and(|station| station.position == position)
+                || self.stations.back().is_some_and(|station| station.position == position))
+    }
+
+    // останавливается ли на станции поезд со схемой `pattern`
+    pub fn stops_at(&self, pattern: ServicePattern, position: (i32, i32)) -> bool {
+        if pattern == ServicePattern::AllStops
+            || self.is_terminal(position)
+            || self.express_stops.contains(&position)
+        {
+            return true;
+        }
+        match pattern {
+            ServicePattern::SkipA | ServicePattern::SkipB => self.skip_group(position) == Some(pattern),
+            _ => false,
+        }
+    }
+
+    fn skip_group(&self, position: (i32, i32)) -> Option<ServicePattern> {
+        self.skip_stops
+            .iter()
+            .find(|(stop, _)| *stop == position)
+            .map(|(_, group)| *group)
     }
 
-    pub fn new_from_points(id: usize, new_points: Vec<(i32, i32)>) -> Self { // новая ветка из вектора станций
-        let curve = CubicCardinalSpline::new_catmull_rom(new_points
-            .iter().map(|&(x,y)| Vec2::new(x as f32, y as f32)).collect::<Vec<Vec2>>())
-            .to_curve().ok();
-        
-        let mut rng = rand::rng();    
+    // новая станция достаётся схеме, которая не останавливается у соседа; у остальных станций схема не меняется
+    fn assign_skip_group(&mut self, position: (i32, i32), neighbour: Option<(i32, i32)>) {
+        let group = match neighbour.and_then(|neighbour| self.skip_group(neighbour)) {
+            Some(ServicePattern::SkipA) => ServicePattern::SkipB,
+            _ => ServicePattern::SkipA,
+        };
+        self.skip_stops.retain(|(stop, _)| *stop != position);
+        self.skip_stops.push((position, group));
+    }
+
+    /// Раздаёт схемам «через одну» станции, у которых схемы ещё нет: по порядку линии, A с первой.
+    /// Нужно новым линиям и линиям из старых сохранений.
+    pub fn fill_skip_groups(&mut self) {
+        let positions: Vec<(i32, i32)> = self.stations.iter().map(|station| station.position).collect();
+        for (index, position) in positions.into_iter().enumerate() {
+            if self.skip_group(position).is_some() {
+                continue;
+            }
+            let group = match index % 2 {
+                0 => ServicePattern::SkipA,
+                _ => ServicePattern::SkipB,
+            };
+            self.skip_stops.push((position, group));
+        }
+    }
 
+    pub fn can_close_ring(&self) -> bool {
+        !self.is_ring && self.stations.len() >= MIN_RING_STATIONS
+    }
+
+    pub fn close_ring(&mut self) {
+        self.is_ring = true;
+        self.update_curve();
+    }
+
+    // убирает станцию, слишком короткое кольцо снова становится обычной линией
+    pub fn remove_station(&mut self, position: (i32, i32)) {
+        self.stations = self.stations
+            .iter().filter(|station| station.position != position).copied().collect();
+        self.express_stops.retain(|stop| *stop != position);
+        self.skip_stops.retain(|(stop, _)| *stop != position);
+        if self.depot == Some(position) {
+            self.depot = None;
+        }
+        if self.stations.len() < MIN_RING_STATIONS {
+            self.is_ring = false;
+        }
+        self.update_curve();
+    }
+
+    pub fn new_from_points(id: usize, name: String, new_points: Vec<(i32, i32)>, rng: &mut impl Rng) -> Self { // новая ветка из вектора станций
         let mut stations = LinkedList::new();
         for point in new_points.iter() {
             stations.push_back(Station::new(*point));
         }
 
-        Self {
-            name: LINE_NAMES[rand::rng().random_range(0..9)].to_string(),
+        let mut line = Self {
+            name,
             id,
             stations: stations,
-            curve,
-            color: Color::hsl(rng.random_range(0..=12) as f32 * 30., 0.75, 0.5)
-        }
+            curve: None,
+            color: Color::hsl(rng.random_range(0..=12) as f32 * 30., 0.75, 0.5),
+            is_ring: false,
+            path: None,
+            headway_secs: None,
+            express_stops: vec![],
+            depot: None,
+            skip_stops: vec![],
+        };
+        line.fill_skip_groups();
+        line.update_curve();
+        line
     }
 
     pub fn push_back(&mut self, point: (i32, i32)) {
+        let neighbour = self.stations.back().map(|station| station.position);
+        self.assign_skip_group(point, neighbour);
         self.stations.push_back(Station::new(point));
         self.update_curve();
     }
 
     pub fn push_front(&mut self, point: (i32, i32)) {
+        let neighbour = self.stations.front().map(|station| station.position);
+        self.assign_skip_group(point, neighbour);
         self.stations.push_front(Station::new(point));
         self.update_curve();
     }
+
+    // станция, следующая за `position`; у кольца за последней идёт первая
+    pub fn next_station(&self, position: (i32, i32)) -> Option<(i32, i32)> {
+        let mut positions = self.stations.iter().map(|station| station.position);
+        let first = positions.clone().next();
+        positions.find(|station| *station == position)?;
+        match positions.next() {
+            Some(next) => Some(next),
+            None if self.is_ring => first,
+            None => None,
+        }
+    }
+
+    /// Перегон, над которым находится точка: станция, с которой он начинается, и ближайшая
+    /// к точке позиция на пути линии. None, если точка дальше `radius` от пути.
+    pub fn segment_at(&self, point: Vec2, radius: f32) -> Option<((i32, i32), Vec2)> {
+        let path = self.path.as_ref()?;
+        let distance = path.project(point);
+        let on_path = path.position(distance);
+        if on_path.distance(point) > radius {
+            return None;
+        }
+        let index = path
+            .station_offsets()
+            .iter()
+            .rposition(|offset| *offset <= distance)?;
+        let from = self.stations.iter().nth(index)?.position;
+        Some((from, on_path))
+    }
+
+    // врезает станцию в перегон после `after`, порядок остальных станций не меняется
+    pub fn insert_after(&mut self, after: (i32, i32), point: (i32, i32)) {
+        let mut stations = LinkedList::new();
+        for station in self.stations.iter() {
+            stations.push_back(*station);
+            if station.position == after {
+                stations.push_back(Station::new(point));
+            }
+        }
+        self.stations = stations;
+        self.assign_skip_group(point, Some(after));
+        self.update_curve();
+    }
 }
 
 #[derive(Component)]
@@ -75,6 +378,25 @@ pub struct SpawnLineCurveEvent {
     pub line_id: usize,
 }
 
+/// Переименовать линию. Имя должно быть непустым и не совпадать с именем другой линии.
+#[derive(Event)]
+pub struct RenameLineEvent {
+    pub line_id: usize,
+    pub name: String,
+}
+
+fn rename_line(mut ev_rename: EventReader<RenameLineEvent>, mut metro: ResMut<Metro>) {
+    for ev in ev_rename.read() {
+        let name = ev.name.trim();
+        if name.is_empty() || metro.lines.iter().any(|line| line.name == name && line.id != ev.line_id) {
+            continue;
+        }
+        if let Some(line) = metro.lines.get_mut(ev.line_id) {
+            line.name = name.to_string();
+        }
+    }
+}
+
 fn spawn_line_curve(
     mut commands: Commands,
     metro: Res<Metro>,
@@ -113,14 +435,53 @@ fn update_line_renderer(
     mut q_line_renderer: Query<(&mut bevy_2d_line::Line, &LineRenderer)>,
 ) {
     for ev in ev_update_line.read() {
-        let (mut line, _) = q_line_renderer.iter_mut().filter(|(_, renderer)| renderer.line_id == ev.line_id).next().unwrap();
+        let Some((mut line, _)) = q_line_renderer.iter_mut().find(|(_, renderer)| renderer.line_id == ev.line_id) else { continue };
         let line_data = &metro.lines[ev.line_id];
-        let Some(ref curve) = line_data.curve else { continue };
+        let Some(ref curve) = line_data.curve else { // линию снесли
+            line.points.clear();
+            line.colors.clear();
+            continue
+        };
         let resolution = 100 * curve.segments().len();
 
         line.points = curve.iter_positions(resolution).collect::<Vec<Vec2>>();
-        while line.colors.len() < line.points.len() {
-            line.colors.push(line_data.color.into());
-        }
+        let points_count = line.points.len();
+        line.colors.resize(points_count, line_data.color.into());
+    }
+}
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn served(line: &MetroLine, pattern: ServicePattern) -> Vec<(i32, i32)> {
+        line.stations
+            .iter()
+            .map(|station| station.position)
+            .filter(|position| line.stops_at(pattern, *position))
+            .collect()
+    }
+
+    #[test]
+    fn infill_station_keeps_skip_stop_groups() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let points = vec![(0, 0), (100, 0), (200, 0), (300, 0), (400, 0)];
+        let mut line = MetroLine::new_from_points(0, "Линия 1".to_string(), points, &mut rng);
+        let skip_a = served(&line, ServicePattern::SkipA);
+        let skip_b = served(&line, ServicePattern::SkipB);
+        assert_eq!(skip_a, vec![(0, 0), (200, 0), (400, 0)]);
+        assert_eq!(skip_b, vec![(0, 0), (100, 0), (300, 0), (400, 0)]);
+
+        line.insert_after((100, 0), (150, 0));
+        let mut skip_a_after = served(&line, ServicePattern::SkipA);
+        let mut skip_b_after = served(&line, ServicePattern::SkipB);
+        // новая станция достаётся одной из схем, у остальных всё по-прежнему
+        assert_ne!(skip_a_after.contains(&(150, 0)), skip_b_after.contains(&(150, 0)));
+        skip_a_after.retain(|position| *position != (150, 0));
+        skip_b_after.retain(|position| *position != (150, 0));
+        assert_eq!(skip_a_after, skip_a);
+        assert_eq!(skip_b_after, skip_b);
     }
-}
\ No newline at end of file
+}
@@ -1,55 +1,65 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, state::app::StatesPlugin};
 
 mod utils;
 
-use bevy_2d_line::LineRenderingPlugin;
-use bevy_lunex::{UiLunexDebugPlugin, UiLunexPlugins};
-
 mod settings;
-use settings::SettingsPlugin;
 
 mod camera;
-use camera::CameraPlugin;
 
 mod map_generation;
-use line::MetroLinePlugin;
-use map_generation::MapGenerationPlugin;
 
 mod station;
-use station::StationPlugin;
 
 mod station_blueprint;
-use station_blueprint::StationBlueprintPlugin;
+
+mod demolition;
 
 mod audio;
-use audio::AudioPlugin;
 
 mod ui;
-use ui::{MainMenuPlugin, StationUIPlugin, SettingsUIPlugin,  TutorialUIPlugin, MoneyUIPlugin, AudioUIPlugin};
 
 mod line;
 
 mod metro;
-use metro::MetroPlugin;
 
 mod train;
-use train::TrainPlugin;
 
 mod cursor;
-use cursor::CursorPlugin;
 
 mod district;
-use district::DistrictPlugin;
 
 mod passenger;
-use passenger::PassengerPlugin;
 
+mod catchment;
 
 mod money;
-use money::MoneyPlugin;
+
+mod save;
+
+mod rng;
+
+mod clock;
+
+mod city_events;
+
+mod routing;
+
+mod signalling;
+
+mod depot;
+
+mod telemetry;
+
+mod simulation;
+use simulation::{HeadlessPlugin, SimulationPlugins};
+
+mod presentation;
+use presentation::PresentationPlugins;
 
 const DISTRICT_CELL_SIZE: f32 = 50.;
 const MAX_DISTRICT_SIZE: usize = 24;
+// по умолчанию безоконная партия длится игровые сутки
+const DEFAULT_HEADLESS_MINUTES: u64 = 24 * 60;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum GameState {
@@ -59,25 +69,46 @@ pub enum GameState {
     Settings,
 }
 
-fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugins(UiLunexPlugins)
-        .add_plugins(CursorPlugin)
-        .add_plugins(MapGenerationPlugin)
-        .init_state::<GameState>()
-        .add_plugins(LineRenderingPlugin)
-        .add_plugins(CameraPlugin)
-        .add_plugins((StationPlugin, StationBlueprintPlugin))
-        .add_plugins(MetroPlugin)
-        .add_plugins(MetroLinePlugin)
-        .add_plugins(TrainPlugin)
-        .add_plugins(AudioPlugin)
-        .add_plugins((MainMenuPlugin, StationUIPlugin, TutorialUIPlugin, MoneyUIPlugin, AudioUIPlugin, SettingsUIPlugin//, UiLunexDebugPlugin::<0>
-            ))
-        .add_plugins(DistrictPlugin)
-        .add_plugins(SettingsPlugin)
-        .add_plugins(PassengerPlugin)
-        .add_plugins(MoneyPlugin)
-        .run();
+// `--seed <число>` фиксирует город и поведение пассажиров
+fn seed() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    let index = args.iter().position(|arg| arg == "--seed")?;
+
+    args.get(index + 1)?.parse().ok()
+}
+
+// `--headless [игровые минуты]` запускает симуляцию без окна
+fn headless_minutes() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    let index = args.iter().position(|arg| arg == "--headless")?;
+
+    Some(
+        args.get(index + 1)
+            .and_then(|minutes| minutes.parse().ok())
+            .unwrap_or(DEFAULT_HEADLESS_MINUTES),
+    )
+}
+
+fn main() -> AppExit {
+    let mut app = App::new();
+    let seed = seed();
+
+    match headless_minutes() {
+        Some(minutes) => {
+            app.add_plugins((MinimalPlugins, StatesPlugin, bevy::log::LogPlugin::default()))
+                .insert_state(GameState::InGame)
+                .enable_state_scoped_entities::<GameState>()
+                .add_plugins(SimulationPlugins { seed })
+                .add_plugins(HeadlessPlugin { minutes });
+        }
+        None => {
+            app.add_plugins(DefaultPlugins)
+                .init_state::<GameState>()
+                .enable_state_scoped_entities::<GameState>()
+                .add_plugins(SimulationPlugins { seed })
+                .add_plugins(PresentationPlugins);
+        }
+    }
+
+    app.run()
 }
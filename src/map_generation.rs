@@ -1,12 +1,12 @@
 use bevy::prelude::*;
 
-use crate::{line::SpawnLineCurveEvent, metro::Metro, station::SpawnStationEvent, train::SpawnTrainEvent, GameState};
+use crate::{line::{ServicePattern, SpawnLineCurveEvent}, metro::Metro, rng::GameRng, save::PendingLoad, station::SpawnStationEvent, train::{SpawnTrainEvent, TrainModel}, GameState};
 
 pub struct MapGenerationPlugin;
 
 impl Plugin for MapGenerationPlugin{
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::InGame), spawn_map);//should i change it to startup back, so it generates events at startup?
+        app.add_systems(OnEnter(GameState::InGame), spawn_map.run_if(not(resource_exists::<PendingLoad>)));//should i change it to startup back, so it generates events at startup?
     }
 }
 
@@ -15,18 +15,20 @@ fn spawn_map(
     mut ev_spawn_station: EventWriter<SpawnStationEvent>,
     mut ev_spawn_train: EventWriter<SpawnTrainEvent>,
     mut ev_spawn_line: EventWriter<SpawnLineCurveEvent>,
+    mut rng: ResMut<GameRng>,
 ) {
-    let line = metro.add_line(vec![]);
+    let line = metro.add_line(vec![], &mut **rng);
 
     let pos1 = (0, 0);
     let pos2 = (100, 0);
     line.push_back(pos1);
     line.push_back(pos2);
+    metro.update_line_edges(0);
 
     ev_spawn_line.send(SpawnLineCurveEvent { line_id: 0 });
 
-    ev_spawn_station.send(SpawnStationEvent { position: pos1, connection: pos2 });
-    ev_spawn_station.send(SpawnStationEvent { position: pos2, connection: pos1 });
+    ev_spawn_station.send(SpawnStationEvent { position: pos1, cost: 0 });
+    ev_spawn_station.send(SpawnStationEvent { position: pos2, cost: 0 });
 
-    ev_spawn_train.send(SpawnTrainEvent { line: 0, station: pos1});
+    ev_spawn_train.send(SpawnTrainEvent { line: 0, station: pos1, model: TrainModel::Standard, pattern: ServicePattern::AllStops, cost: 0 });
 }
\ No newline at end of file
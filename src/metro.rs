@@ -1,60 +1,138 @@
 use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::{line::MetroLine, station::Station, utils::graph::Graph};
+use crate::{
+    line::MetroLine,
+    station::Station,
+    utils::{graph::Graph, naming::numbered_name},
+};
 
 pub struct MetroPlugin;
 
 impl Plugin for MetroPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Metro>();
+    }
+}
+
+pub struct MetroPresentationPlugin;
+
+impl Plugin for MetroPresentationPlugin {
+    fn build(&self, app: &mut App) {
         app.init_gizmo_group::<MetroLineGizmos>();
         app.add_systems(Startup, config_gizmos);
     }
 }
 
+/// Перегон линии в графе станций. Поезд, который идёт по линии вперёд, въезжает в него со станции `from`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Segment {
+    pub line: usize,
+    pub from: (i32, i32),
+}
+
 #[derive(Default, Resource)]
 pub struct Metro {
-    pub stations: Graph<Station>,
+    pub stations: Graph<Station, Segment>, // вес ребра - длина перегона
     pub lines: Vec<MetroLine>,
 }
 
 impl Metro {
-    pub fn add_line(&mut self, points: Vec<(i32, i32)>) -> &mut MetroLine {
+    pub fn add_line(&mut self, points: Vec<(i32, i32)>, rng: &mut impl Rng) -> &mut MetroLine {
         let id = self.lines.len();
-        println!("LINE ID IN ADD LINE - {}", id);
-        let line = MetroLine::new_from_points(id, points);
+        // номер линии не повторяется, даже если игрок переименовал какую-то линию в «Линию N»
+        let taken: Vec<String> = self.lines.iter().map(|line| line.name.clone()).collect();
+        let line = MetroLine::new_from_points(id, numbered_name("Линия", 1, &taken), points, rng);
         self.lines.push(line);
+        self.update_line_edges(id);
         &mut self.lines[id]
     }
-    pub fn find_line_by_station(&mut self, station_id: (i32, i32)) -> Option<&MetroLine> {
-        self.lines
+
+    // перестраивает перегоны линии в графе станций, вызывать после любого изменения линии
+    pub fn update_line_edges(&mut self, line_id: usize) {
+        self.stations.retain_edges(|edge| edge.data.line != line_id);
+
+        let positions: Vec<(i32, i32)> = self.lines[line_id]
+            .stations
             .iter()
-            .filter(|line| {
-                line.stations
-                    .iter()
-                    .filter(|station| station.position == station_id)
-                    .next()
-                    .is_some()
-            })
-            .next()
+            .map(|station| station.position)
+            .collect();
+
+        for position in positions.iter() {
+            self.stations.insert(*position, Station::new(*position));
+        }
+
+        let mut segments: Vec<((i32, i32), (i32, i32))> =
+            positions.windows(2).map(|segment| (segment[0], segment[1])).collect();
+        if self.lines[line_id].is_ring {
+            segments.push((*positions.last().unwrap(), positions[0]));
+        }
+
+        for (from, to) in segments {
+            let length = Vec2::new(from.0 as f32, from.1 as f32)
+                .distance(Vec2::new(to.0 as f32, to.1 as f32));
+            self.stations.connect(from, to, length, Segment { line: line_id, from });
+        }
     }
-    pub fn find_station(&mut self, station_id: (i32, i32)) -> Option<&Station> {
-        self.find_line_by_station(station_id)
-            .unwrap()
+    pub fn find_station(&self, station_id: (i32, i32)) -> Option<&Station> {
+        self.stations.get(station_id)
+    }
+
+    // линии, которые проходят через станцию, по возрастанию id
+    pub fn lines_at(&self, position: (i32, i32)) -> impl Iterator<Item = &MetroLine> {
+        let mut line_ids: Vec<usize> = self.stations.neighbours(position).map(|edge| edge.data.line).collect();
+        line_ids.sort();
+        line_ids.dedup();
+        line_ids.into_iter().map(|line_id| &self.lines[line_id])
+    }
+
+    // все станции линии, без порядка
+    pub fn stations_on(&self, line_id: usize) -> Vec<(i32, i32)> {
+        let mut positions: Vec<(i32, i32)> = self
             .stations
-            .iter()
-            .filter(|station| station.position == station_id)
-            .next()
+            .edges()
+            .filter(|(_, edge)| edge.data.line == line_id)
+            .flat_map(|(position, edge)| [position, edge.to])
+            .collect();
+        positions.sort();
+        positions.dedup();
+        positions
+    }
+
+    // соседняя станция линии, к которой поезд едет в направлении `direction`; None за конечной
+    pub fn next_station(&self, line_id: usize, position: (i32, i32), direction: Direction) -> Option<(i32, i32)> {
+        self.stations
+            .neighbours(position)
+            .find(|edge| {
+                edge.data.line == line_id
+                    && (edge.data.from == position) == (direction == Direction::Forwards)
+            })
+            .map(|edge| edge.to)
+    }
+
+    // пересадочная станция - общая для нескольких линий
+    pub fn is_interchange(&self, position: (i32, i32)) -> bool {
+        self.lines_at(position).nth(1).is_some()
     }
 }
 
-#[derive(Default, PartialEq, Copy, Clone)]
+#[derive(Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum Direction {
     #[default]
     Forwards,
     Backwards,
 }
 
+impl Direction {
+    pub fn reversed(&self) -> Self {
+        match self {
+            Direction::Forwards => Direction::Backwards,
+            Direction::Backwards => Direction::Forwards,
+        }
+    }
+}
+
 #[derive(Default, Reflect, GizmoConfigGroup)]
 struct MetroLineGizmos {}
 
@@ -62,3 +140,35 @@ fn config_gizmos(mut config_store: ResMut<GizmoConfigStore>) {
     let (config, _) = config_store.config_mut::<MetroLineGizmos>();
     config.line_width = 5.;
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn next_station_follows_line_order() {
+        let mut metro = Metro::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        metro.add_line(vec![(0, 0), (100, 0), (200, 0)], &mut rng);
+        metro.add_line(vec![(100, 0), (100, 100)], &mut rng);
+
+        assert_eq!(metro.next_station(0, (0, 0), Direction::Forwards), Some((100, 0)));
+        assert_eq!(metro.next_station(0, (100, 0), Direction::Backwards), Some((0, 0)));
+        assert_eq!(metro.next_station(0, (200, 0), Direction::Forwards), None);
+        assert_eq!(metro.next_station(1, (100, 0), Direction::Forwards), Some((100, 100)));
+
+        let lines: Vec<usize> = metro.lines_at((100, 0)).map(|line| line.id).collect();
+        assert_eq!(lines, vec![0, 1]);
+        assert!(metro.is_interchange((100, 0)));
+        assert_eq!(metro.stations_on(0), vec![(0, 0), (100, 0), (200, 0)]);
+
+        // у кольца за последней станцией снова первая
+        metro.lines[0].close_ring();
+        metro.update_line_edges(0);
+        assert_eq!(metro.next_station(0, (200, 0), Direction::Forwards), Some((0, 0)));
+        assert_eq!(metro.next_station(0, (0, 0), Direction::Backwards), Some((200, 0)));
+    }
+}
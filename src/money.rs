@@ -1,20 +1,110 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock, metro::Metro, simulation::SimulationSet, train::Train, ui::MoneyRedrawEvent,
+    GameState,
+};
 
 pub struct MoneyPlugin;
 
-pub const TRAIN_COST: u32 = 50;
+// вагон, прицепленный к уже купленному поезду
+pub const CAR_COST: i64 = 30;
+// перестановка поезда на другую линию
+pub const TRAIN_TRANSFER_COST: i64 = 20;
+// при сносе возвращается половина стоимости постройки
+const REFUND_PERCENT: i64 = 50;
+const STARTING_MONEY: i64 = 1000;
+
+// расходы за игровой час
+const CAR_OPERATING_COST: i64 = 1;
+const STATION_MAINTENANCE_COST: i64 = 1;
+const LINE_UPKEEP_COST: i64 = 3;
+// сколько игровых часов можно жить в долг, прежде чем метро обанкротится
+const BANKRUPTCY_GRACE_HOURS: u32 = 24;
 
 impl Plugin for MoneyPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Money>();
+        app.init_resource::<Money>()
+            .init_resource::<Finances>()
+            .add_event::<MoneyRedrawEvent>();
+        app.add_systems(OnExit(GameState::MainMenu), reset_money);
+        app.add_systems(
+            Update,
+            (charge_operating_costs, check_bankruptcy)
+                .chain()
+                .in_set(SimulationSet::Economy)
+                .run_if(in_state(GameState::InGame)),
+        );
     }
 }
 
-#[derive(Resource)]
-pub struct Money(pub u32);
+#[derive(Resource, Clone, Copy)]
+pub struct Money(pub i64);
+
+pub fn refund(cost: i64) -> i64 {
+    cost * REFUND_PERCENT / 100
+}
 
 impl Default for Money {
     fn default() -> Self {
-        Self(99999)
+        Self(STARTING_MONEY)
+    }
+}
+
+/// Учёт регулярных расходов: за какой час уже заплатили и с какого часа баланс в минусе.
+#[derive(Resource, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Finances {
+    charged_hours: u32,
+    in_debt_since: Option<u32>,
+}
+
+fn reset_money(mut commands: Commands) {
+    commands.insert_resource(Money::default());
+    commands.insert_resource(Finances::default());
+}
+
+fn charge_operating_costs(
+    clock: Res<GameClock>,
+    metro: Res<Metro>,
+    q_train: Query<&Train>,
+    mut money: ResMut<Money>,
+    mut finances: ResMut<Finances>,
+    mut change_money_ui: EventWriter<MoneyRedrawEvent>,
+) {
+    // первый час партии бесплатный, дальше платим за каждый начавшийся
+    if finances.charged_hours == 0 {
+        finances.charged_hours = clock.total_hours();
+    }
+    if clock.total_hours() <= finances.charged_hours {
+        return;
+    }
+    finances.charged_hours = clock.total_hours();
+
+    let lines = metro.lines.iter().filter(|line| !line.stations.is_empty()).count() as i64;
+    let cars: usize = q_train.iter().map(|train| train.cars).sum();
+    let costs = cars as i64 * CAR_OPERATING_COST
+        + metro.stations.len() as i64 * STATION_MAINTENANCE_COST
+        + lines * LINE_UPKEEP_COST;
+
+    money.0 -= costs;
+    change_money_ui.send(MoneyRedrawEvent);
+}
+
+fn check_bankruptcy(
+    clock: Res<GameClock>,
+    money: Res<Money>,
+    mut finances: ResMut<Finances>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if money.0 >= 0 {
+        finances.in_debt_since = None;
+        return;
+    }
+
+    let in_debt_since = *finances.in_debt_since.get_or_insert(clock.total_hours());
+    if clock.total_hours() - in_debt_since >= BANKRUPTCY_GRACE_HOURS {
+        error!("BANKRUPT");
+        next_state.set(GameState::MainMenu);
     }
 }
@@ -1,12 +1,21 @@
-use std::{collections::VecDeque, time::Duration};
+use std::time::Duration;
 
-use bevy::{prelude::*, state, time::common_conditions::on_timer, utils::HashMap};
+use bevy::{prelude::*, time::common_conditions::on_timer, utils::HashMap};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    catchment::Catchments,
+    city_events::OutOfService,
+    clock::{DayPeriod, GameClock},
     district::{DistrictMap, DistrictType},
     metro::Metro,
-    station::{self, Station, StationButton},
-    GameState, DISTRICT_CELL_SIZE,
+    rng::GameRng,
+    routing::{alighting_station, find_route, RouteStep, Service},
+    simulation::SimulationSet,
+    station::{Station, StationButton},
+    train::Train,
+    GameState,
 };
 
 pub struct PassengerPlugin;
@@ -14,25 +23,44 @@ pub struct PassengerPlugin;
 impl Plugin for PassengerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PassengerDatabase>();
-        app.add_event::<AddPassengerEvent>();
+        app.add_event::<AddPassengerEvent>()
+            .add_event::<SendPassengersEvent>();
         app.add_systems(
             Update,
             (
                 add_passengers,
                 decide_where_to_go,
+                send_passengers,
                 start_moving,
                 fill_passenger_pool
                     // не слишком часто делаем проверки на заполненный пул мест пассажира
                     .run_if(on_timer(Duration::from_millis(100))),
-                stop_moving,
+                settle_arrivals,
             )
+                .chain()
+                .in_set(SimulationSet::Passengers)
                 .run_if(in_state(GameState::InGame)),
         );
-        app.add_systems(Update, monitor_first_guy.run_if(on_timer(Duration::from_secs(5))));
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+// расписание поездок: в какое время суток, откуда, куда и сколько раз в игровой час.
+// утром едут на работу, вечером с работы домой и развлекаться, ночью почти никто не ездит
+const SCHEDULE: [(DayPeriod, PassengerDesire, PassengerDesire, f32); 11] = [
+    (DayPeriod::MorningPeak, PassengerDesire::Home, PassengerDesire::Work, 1.5),
+    (DayPeriod::Day, PassengerDesire::Home, PassengerDesire::Entertainment, 0.1),
+    (DayPeriod::Day, PassengerDesire::Work, PassengerDesire::Entertainment, 0.05),
+    (DayPeriod::Day, PassengerDesire::Entertainment, PassengerDesire::Home, 0.3),
+    (DayPeriod::EveningPeak, PassengerDesire::Work, PassengerDesire::Home, 1.),
+    (DayPeriod::EveningPeak, PassengerDesire::Work, PassengerDesire::Entertainment, 0.5),
+    (DayPeriod::EveningPeak, PassengerDesire::Entertainment, PassengerDesire::Home, 0.3),
+    (DayPeriod::Evening, PassengerDesire::Work, PassengerDesire::Home, 0.8),
+    (DayPeriod::Evening, PassengerDesire::Entertainment, PassengerDesire::Home, 0.8),
+    (DayPeriod::Night, PassengerDesire::Work, PassengerDesire::Home, 0.5),
+    (DayPeriod::Night, PassengerDesire::Entertainment, PassengerDesire::Home, 0.5),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum PassengerDesire {
     Home,
     Work,
@@ -51,17 +79,202 @@ impl std::fmt::Display for PassengerDesire {
     }
 }
 
-#[derive(Clone, PartialEq)]
+/// Где сейчас пассажир. Состояние - единственный источник правды о его местоположении:
+/// списки `passenger_ids` у районов, станций и поездов только повторяют его.
+/// В пути между ними (`WalkingToStation`, `Transferring`, `Arrived`) пассажир ни в одном списке не числится.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Journey {
+    AtDistrict { district: usize },
+    WalkingToStation { station: (i32, i32) },
+    Waiting { station: (i32, i32) },
+    Riding { train: Entity },
+    // вышел из поезда и переходит на другую платформу этой же станции
+    Transferring { station: (i32, i32) },
+    // вышел на конечной станции маршрута или отменил поездку и идёт в район
+    Arrived { district: usize },
+}
+
+// в старых сохранениях состояния нет, его восстанавливают по спискам после загрузки
+impl Default for Journey {
+    fn default() -> Self {
+        Journey::AtDistrict { district: 0 }
+    }
+}
+
+impl Journey {
+    // все допустимые переходы; в любой другой пассажир попасть не может
+    pub fn can_become(&self, next: &Journey) -> bool {
+        use Journey::*;
+        match (*self, *next) {
+            (AtDistrict { .. }, WalkingToStation { .. }) => true,
+            (WalkingToStation { station }, Waiting { station: to })
+            | (Transferring { station }, Waiting { station: to }) => station == to,
+            (Waiting { .. }, Riding { .. }) => true,
+            (Riding { .. }, Transferring { .. }) => true,
+            (Arrived { district }, AtDistrict { district: to }) => district == to,
+            // доехать или отказаться от поездки можно откуда угодно, кроме района
+            (AtDistrict { .. } | Arrived { .. }, Arrived { .. }) => false,
+            (_, Arrived { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Passenger {
     pub current_desire: PassengerDesire,
     pub last_visited_district: usize,
     pub district_ids: [usize; 3],
-    pub route: Vec<Station>,
+    pub route: Vec<RouteStep>,
+    #[serde(default)] // меняется только через `advance`
+    journey: Journey,
 }
 
-#[derive(Resource, Default)]
+impl Passenger {
+    pub fn journey(&self) -> Journey {
+        self.journey
+    }
+
+    /// Переводит пассажира в состояние `next`, если такой переход допустим. Вызывающий после
+    /// успешного перехода сам переносит пассажира между списками районов, станций и поездов.
+    pub fn advance(&mut self, next: Journey) -> bool {
+        if !self.journey.can_become(&next) {
+            error!("passenger can't go from {:?} to {:?}", self.journey, next);
+            return false;
+        }
+        self.journey = next;
+        true
+    }
+
+    // после загрузки у поездов новые сущности, поэтому едущих заново привязываем к поезду
+    pub fn rebind(&mut self, journey: Journey) {
+        self.journey = journey;
+    }
+
+    // поезд, в котором пассажир ехал, не восстановился после загрузки: поездка отменяется
+    pub fn rebind_cancelled(&mut self) {
+        self.journey = Journey::Arrived {
+            district: self.last_visited_district,
+        };
+        self.cancel_trip();
+    }
+
+    // район, куда пассажир сейчас едет
+    pub fn destination(&self) -> usize {
+        self.district_ids[self.current_desire as usize]
+    }
+
+    // поездка отменена, пассажир возвращается в район, откуда выехал
+    pub fn cancel_trip(&mut self) {
+        self.route.clear();
+        if let Some(index) = self
+            .district_ids
+            .iter()
+            .position(|id| *id == self.last_visited_district)
+        {
+            self.current_desire = [
+                PassengerDesire::Home,
+                PassengerDesire::Work,
+                PassengerDesire::Entertainment,
+            ][index];
+        }
+        // кто ещё не вышел из района, там и остаётся
+        if !matches!(self.journey, Journey::AtDistrict { .. } | Journey::Arrived { .. }) {
+            self.advance(Journey::Arrived {
+                district: self.last_visited_district,
+            });
+        }
+    }
+
+    // индекс в маршруте станции, где пассажир выйдет из нынешнего поезда
+    fn leg_end(&self) -> Option<usize> {
+        let end = alighting_station(&self.route)?;
+        (1..self.route.len()).find(|index| self.route[*index].station.position == end)
+    }
+
+    /// Поезд, в котором едет пассажир, встал на станции `station`. Пассажир выходит на своей
+    /// станции высадки или раньше, если поезд до неё уже не довезёт (`train_reaches_alighting`).
+    /// Промежуточные остановки маршрут не трогают. Возвращает true, если пассажир вышел.
+    pub fn alight(&mut self, station: (i32, i32), train_reaches_alighting: bool) -> bool {
+        let Some(end) = self.leg_end() else {
+            return false;
+        };
+        // ищем станцию только на нынешнем перегоне маршрута, дальше она может встретиться на другой линии
+        let Some(index) = (1..=end).find(|index| self.route[*index].station.position == station) else {
+            return false;
+        };
+        if index < end && train_reaches_alighting {
+            return false;
+        }
+
+        let next = match index == self.route.len() - 1 {
+            true => Journey::Arrived {
+                district: self.destination(),
+            },
+            false => Journey::Transferring { station },
+        };
+        if !self.advance(next) {
+            return false;
+        }
+        self.route.drain(..index);
+        true
+    }
+
+    // поезд снимают с линии: пассажир выходит и ждёт следующего, если станция по пути, иначе едет домой
+    pub fn leave_train(&mut self, station: (i32, i32)) {
+        let on_leg = self
+            .leg_end()
+            .and_then(|end| (0..=end).find(|index| self.route[*index].station.position == station));
+        match on_leg {
+            Some(index) if self.advance(Journey::Transferring { station }) => {
+                self.route.drain(..index);
+            }
+            _ => self.cancel_trip(),
+        }
+    }
+
+    /// В перегон `from`-`to` линии `line` врезали станцию `stop`: маршрут, который едет по этому
+    /// перегону, теперь проезжает и её. Пересадки и станции высадки не меняются.
+    pub fn insert_stop(&mut self, line: usize, from: (i32, i32), to: (i32, i32), stop: (i32, i32)) {
+        let Some(index) = self.route.windows(2).position(|pair| {
+            let (a, b) = (pair[0].station.position, pair[1].station.position);
+            pair[0].line == line && ((a, b) == (from, to) || (a, b) == (to, from))
+        }) else {
+            return;
+        };
+        self.route.insert(
+            index + 1,
+            RouteStep {
+                station: Station::new(stop),
+                line,
+                transfer: false,
+            },
+        );
+    }
+
+    // пассажир дошёл из района или с конечной в свой район; возвращает, в какой
+    pub fn settle(&mut self) -> Option<usize> {
+        let Journey::Arrived { district } = self.journey else {
+            return None;
+        };
+        if !self.advance(Journey::AtDistrict { district }) {
+            return None;
+        }
+        self.last_visited_district = district;
+        self.route.clear();
+        Some(district)
+    }
+}
+
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
 pub struct PassengerDatabase(pub HashMap<usize, Passenger>);
 
+#[derive(Event)]
+pub struct SendPassengersEvent {
+    pub district_id: usize,
+    pub count: usize,
+}
+
 #[derive(Event)]
 pub struct AddPassengerEvent {
     pub(crate) district_id: usize,
@@ -78,6 +291,9 @@ fn add_passengers(
             last_visited_district: ev.district_id,
             district_ids: [ev.district_id, 0, 0], // домашний район - район, в котором он создался
             route: vec![],
+            journey: Journey::AtDistrict {
+                district: ev.district_id,
+            },
         };
         let passenger_id = database.0.len();
         district_map.districts[ev.district_id]
@@ -87,8 +303,16 @@ fn add_passengers(
     }
 }
 
-fn fill_passenger_pool(mut database: ResMut<PassengerDatabase>, district_map: Res<DistrictMap>) {
-    for (_, passenger) in database.0.iter_mut() {
+fn fill_passenger_pool(
+    mut database: ResMut<PassengerDatabase>,
+    district_map: Res<DistrictMap>,
+    mut rng: ResMut<GameRng>,
+) {
+    // случайность тянется по пассажиру за раз, поэтому порядок обхода должен быть одинаковым при одном сиде
+    let mut ids: Vec<usize> = database.0.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let passenger = database.0.get_mut(&id).unwrap();
         if passenger.district_ids[1] == 0 {
             let work_districts: Vec<usize> = district_map
                 .districts
@@ -101,7 +325,7 @@ fn fill_passenger_pool(mut database: ResMut<PassengerDatabase>, district_map: Re
                 continue;
             }
 
-            let district_id = work_districts[rand::random_range(0..work_districts.len())];
+            let district_id = work_districts[rng.random_range(0..work_districts.len())];
             passenger.district_ids[1] = district_id;
         }
 
@@ -118,268 +342,379 @@ fn fill_passenger_pool(mut database: ResMut<PassengerDatabase>, district_map: Re
             }
 
             let district_id =
-                entertainment_districts[rand::random_range(0..entertainment_districts.len())];
+                entertainment_districts[rng.random_range(0..entertainment_districts.len())];
             passenger.district_ids[2] = district_id;
         }
     }
 }
 
+fn count_trains(q_train: &Query<&Train>) -> HashMap<Service, usize> {
+    let mut trains_per_service: HashMap<Service, usize> = HashMap::new();
+    for train in q_train.iter() {
+        *trains_per_service.entry((train.line, train.pattern)).or_insert(0) += 1;
+    }
+    trains_per_service
+}
+
+// прокладывает маршрут туда, куда хочется; нет пути - остаёмся на месте и попробуем в другой раз
+fn plan_trip(
+    passenger: &mut Passenger,
+    desire: PassengerDesire,
+    metro: &Metro,
+    catchments: &Catchments,
+    trains_per_service: &HashMap<Service, usize>,
+) -> bool {
+    let destination_district_id = passenger.district_ids[desire as usize];
+    // из района идут к ближайшей станции в охвате, дальние станции дороже на время пешком
+    let origins = catchments.stations_for(passenger.last_visited_district);
+    let targets: Vec<(i32, i32)> = catchments
+        .stations_for(destination_district_id)
+        .iter()
+        .map(|(station, _)| *station)
+        .collect();
+
+    let Some(route) = find_route(metro, origins, &targets, trains_per_service) else {
+        return false;
+    };
+
+    passenger.current_desire = desire;
+    passenger.route = route;
+    true
+}
+
 fn decide_where_to_go(
     mut database: ResMut<PassengerDatabase>,
-    district_map: Res<DistrictMap>,
+    catchments: Res<Catchments>,
     metro: Res<Metro>,
+    mut rng: ResMut<GameRng>,
+    clock: Res<GameClock>,
+    q_train: Query<&Train>,
 ) {
-    for (_, passenger) in database.0.iter_mut() {
-        // println!("{:?}", passenger.district_ids);
-
-        if passenger.route.len() != 0
+    let trains_per_service = count_trains(&q_train);
+
+    let mut ids: Vec<usize> = database.0.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let passenger = database.0.get_mut(&id).unwrap();
+        if !matches!(passenger.journey, Journey::AtDistrict { .. })
+            || !passenger.route.is_empty()
             || passenger.district_ids[1] == 0
             || passenger.district_ids[2] == 0
         {
             continue;
         }
 
-        let random_desire: PassengerDesire;
-        match passenger.current_desire {
-            PassengerDesire::Entertainment => {
-                random_desire = match rand::random_bool(0.5) {
-                    false => PassengerDesire::Home,
-                    true => PassengerDesire::Work,
-                };
-            }
-            PassengerDesire::Home => {
-                random_desire = match rand::random_bool(0.5) {
-                    false => PassengerDesire::Entertainment,
-                    true => PassengerDesire::Work,
-                };
-            }
-            PassengerDesire::Work => {
-                random_desire = match rand::random_bool(0.5) {
-                    false => PassengerDesire::Home,
-                    true => PassengerDesire::Entertainment,
-                };
+        // куда и с какой вероятностью хочется поехать в это время суток
+        let mut random_desire = None;
+        for (period, from, to, trips_per_hour) in SCHEDULE.iter() {
+            if *period != clock.period() || *from != passenger.current_desire {
+                continue;
             }
-        }
-        passenger.current_desire = random_desire;
-
-        let destination_district_id = passenger.district_ids[random_desire as usize];
-        let district = &district_map.districts[destination_district_id];
-
-        for line in metro.lines.iter() {
-            for station in line.stations.iter() {
-                for cell in district.cells.iter() {
-                    let cell_position =
-                        Vec2::new(cell.0 as f32, cell.1 as f32) * DISTRICT_CELL_SIZE;
-                    let station_position =
-                        Vec2::new(station.position.0 as f32, station.position.1 as f32);
-                    let distance = cell_position.distance(station_position);
-
-                    if distance > DISTRICT_CELL_SIZE / 2. {
-                        continue;
-                    }
-
-                    passenger.route.push(*station);
-                    break;
-                }
+
+            let chance = (trips_per_hour * clock.delta_minutes() / 60.).min(1.);
+            if rng.random_bool(chance as f64) {
+                random_desire = Some(*to);
+                break;
             }
         }
+        let Some(random_desire) = random_desire else {
+            continue;
+        };
+
+        plan_trip(passenger, random_desire, &metro, &catchments, &trains_per_service);
     }
 }
 
-fn start_moving(
+// толпа свободных жителей едет развлекаться в один район, например на матч
+fn send_passengers(
+    mut ev_send: EventReader<SendPassengersEvent>,
     mut database: ResMut<PassengerDatabase>,
-    mut district_map: ResMut<DistrictMap>,
-    metro: ResMut<Metro>,
-    mut q_station_button: Query<(&mut StationButton, &Station)>,
+    catchments: Res<Catchments>,
+    metro: Res<Metro>,
+    q_train: Query<&Train>,
 ) {
-    for district in district_map
-        .districts
-        .iter_mut()
-        .filter(|dist| dist.passenger_ids.len() > 0)
-    {
-        for id in district.passenger_ids.clone().iter() {
-            let passenger = database.0.get(id).unwrap();
-            if passenger.route.len() == 0 {
-                continue;
-            }
+    let trains_per_service = count_trains(&q_train);
 
-            let mut starting_line = None;
-            let mut starting_station = None;
-            let destination_station = database.0.get(id).unwrap().route[0];
+    for ev in ev_send.read() {
+        let mut ids: Vec<usize> = database.0.keys().copied().collect();
+        ids.sort();
 
-            // WTF IS THIS SHIT
-            let mut destination_line = None;
-            for line in metro.lines.iter() {
-                if line.stations.contains(&destination_station) {
-                    destination_line = Some(line.id);
-                }
-            }
-            if destination_line.is_none() {
-                continue;
-            }
-
-            'line_loop: for line in metro.lines.iter() {
-                for station in line.stations.iter() {
-                    for cell in district.cells.iter() {
-                        let cell_position =
-                            Vec2::new(cell.0 as f32, cell.1 as f32) * DISTRICT_CELL_SIZE;
-                        let station_position =
-                            Vec2::new(station.position.0 as f32, station.position.1 as f32);
-                        let distance = cell_position.distance(station_position);
-
-                        if distance > DISTRICT_CELL_SIZE / 2. {
-                            continue;
-                        }
-
-                        let Some((mut station_button, _)) = q_station_button
-                            .iter_mut()
-                            .filter(|(_, &st)| station.position == st.position)
-                            .next()
-                        else {
-                            continue;
-                        };
-
-                        if station_button.passenger_ids.len() >= 12 {
-                            continue;
-                        }
-
-                        station_button.passenger_ids.push(*id);
-
-                        let Some(remove_index) = district
-                            .passenger_ids
-                            .iter()
-                            .position(|pass_id| *pass_id == *id)
-                        else {
-                            continue;
-                        };
-
-                        district.passenger_ids.remove(remove_index);
-
-                        starting_line = Some(line.id);
-                        starting_station = Some(station);
-
-                        break 'line_loop;
-                    }
-                }
-            }
-
-            if starting_line.is_none() {
-                continue;
+        let mut sent = 0;
+        for id in ids {
+            if sent >= ev.count {
+                break;
             }
 
-            if starting_station.is_none() {
+            let passenger = database.0.get_mut(&id).unwrap();
+            if !matches!(passenger.journey, Journey::AtDistrict { .. })
+                || !passenger.route.is_empty()
+                || passenger.district_ids[2] == 0
+                || passenger.last_visited_district == ev.district_id
+            {
                 continue;
             }
 
-            let mut queue = VecDeque::new();
-            queue.push_back(starting_line.unwrap());
-
-            let mut visited = HashMap::new();
-            let mut last_station;
-
-            let mut visited_lines: Vec<usize> = vec![];
-
-            while let Some(line_id) = queue.pop_front() {
-                if line_id == destination_line.unwrap() {
-                    let mut path = vec![destination_station];
-                    let mut current = *starting_station.unwrap();
-                    while let Some(&Some(prev)) = visited.get(&current.position) {
-                        path.push(prev);
-                        current = prev.clone();
-                    }
-                    path.reverse();
-                    database.0.get_mut(id).unwrap().route = path;
-                    break;
-                }
-
-                for line in metro
-                    .lines
-                    .iter()
-                    .filter(|&current| *current != metro.lines[line_id])
-                {
-                    if visited_lines.contains(&line.id) {
-                        continue;
-                    }
-
-                    for station in line.stations.iter() {
-                        last_station = station;
-
-                        if metro.lines[line_id].stations.contains(station) {
-                            if visited.contains_key(&station.position) {
-                                continue;
-                            }
-
-                            queue.push_back(line.id);
-                            visited_lines.push(line.id);
-                            visited.insert(station.position, Some(*last_station));
-                            break;
-                        }
-                    }
-                }
+            // болельщик и дальше будет ходить развлекаться в этот район
+            let favourite = passenger.district_ids[2];
+            passenger.district_ids[2] = ev.district_id;
+            if plan_trip(passenger, PassengerDesire::Entertainment, &metro, &catchments, &trains_per_service) {
+                sent += 1;
+            } else {
+                passenger.district_ids[2] = favourite;
             }
-
-            // while let Some(line_id) = queue.pop_front() {
-            //     if line_id == destination_line.unwrap() {
-            //         let mut path = vec![destination_station];
-            //         let mut current = *starting_station.unwrap();
-            //         while let Some(&Some(prev)) = visited.get(&current.position) {
-            //             path.push(prev);
-            //             current = prev.clone();
-            //         }
-            //         path.reverse();
-            //         database.0.get_mut(id).unwrap).route = path;
-            //         break; // success
-            //     }
         }
     }
 }
 
-fn stop_moving(
-    database: Res<PassengerDatabase>,
+// жители с маршрутом выходят из района, а дошедшие до станции встают ждать поезд
+fn start_moving(
+    mut database: ResMut<PassengerDatabase>,
     mut district_map: ResMut<DistrictMap>,
-    metro: Res<Metro>,
-    mut q_station_button: Query<(&mut StationButton, &Station)>,
+    mut q_station_button: Query<(&mut StationButton, &Station, Has<OutOfService>)>,
 ) {
-    for line in metro.lines.iter() {
-        for station in line.stations.iter() {
-            let Some((mut station_button, _)) = q_station_button
-                .iter_mut()
-                .filter(|(_, &st)| station.position == st.position)
-                .next()
-            else {
-                continue;
+    // жители района не меняют его клеток, охват станций из-за них не пересчитывается
+    for district in district_map.bypass_change_detection().districts.iter_mut() {
+        district.passenger_ids.retain(|id| {
+            let passenger = database.0.get_mut(id).unwrap();
+            let Some(first_step) = passenger.route.first() else {
+                return true;
             };
+            !passenger.advance(Journey::WalkingToStation {
+                station: first_step.station.position,
+            })
+        });
+    }
 
-            for passenger_id in station_button.passenger_ids.clone().iter() {
-                let Some(passenger) = database.0.get(passenger_id) else {
-                    continue;
-                };
+    // порядок важен, когда на станции мало мест, поэтому не полагаемся на порядок HashMap
+    let mut ids: Vec<usize> = database.0.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let passenger = database.0.get_mut(&id).unwrap();
+        let (station, from_street) = match passenger.journey {
+            Journey::WalkingToStation { station } => (station, true),
+            Journey::Transferring { station } => (station, false),
+            _ => continue,
+        };
+        let Some((mut station_button, _, is_closed)) = q_station_button
+            .iter_mut()
+            .find(|(_, st, _)| st.position == station)
+        else {
+            continue;
+        };
 
-                if passenger.route.len() != 0 {
-                    continue;
-                }
+        // пересаживающиеся уже внутри, а с улицы на закрытую или переполненную станцию не войти
+        if from_street
+            && (is_closed || station_button.is_overcrowded())
+        {
+            continue;
+        }
 
-                let district = &mut district_map.districts
-                    [passenger.district_ids[passenger.current_desire as usize]];
+        if passenger.advance(Journey::Waiting { station }) {
+            station_button.passenger_ids.push(id);
+        }
+    }
+}
 
-                district.passenger_ids.push(*passenger_id);
+// доехавшие до района и отменившие поездку снова живут в районе
+fn settle_arrivals(mut database: ResMut<PassengerDatabase>, mut district_map: ResMut<DistrictMap>) {
+    let mut ids: Vec<usize> = database.0.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        if let Some(district) = database.0.get_mut(&id).unwrap().settle() {
+            district_map.bypass_change_detection().districts[district].passenger_ids.push(id);
+        }
+    }
+}
 
-                let remove_index = station_button
-                    .passenger_ids
-                    .iter()
-                    .position(|id| id == passenger_id)
-                    .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::district::District;
 
-                station_button.passenger_ids.remove(remove_index);
-            }
+    const HOME: usize = 1;
+    const WORK: usize = 2;
+
+    fn step(position: (i32, i32), line: usize, transfer: bool) -> RouteStep {
+        RouteStep {
+            station: Station::new(position),
+            line,
+            transfer,
         }
     }
-}
 
-fn monitor_first_guy(database: Res<PassengerDatabase>) {
-    let Some(passenger) = database.0.get(&0) else {
-        return;
-    };
+    // едет на работу: A -линия 0-> B, пересадка, B -линия 1-> D -> C
+    fn commuter() -> Passenger {
+        Passenger {
+            current_desire: PassengerDesire::Work,
+            last_visited_district: HOME,
+            district_ids: [HOME, WORK, 3],
+            route: vec![
+                step((0, 0), 0, false),
+                step((100, 0), 1, true),
+                step((100, 100), 1, false),
+                step((100, 200), 1, false),
+            ],
+            journey: Journey::AtDistrict { district: HOME },
+        }
+    }
+
+    fn board_at(passenger: &mut Passenger, station: (i32, i32), train: Entity) {
+        assert!(passenger.advance(Journey::WalkingToStation { station })
+            || passenger.advance(Journey::Waiting { station }));
+        if passenger.journey() != (Journey::Waiting { station }) {
+            assert!(passenger.advance(Journey::Waiting { station }));
+        }
+        assert!(passenger.advance(Journey::Riding { train }));
+    }
+
+    #[test]
+    fn commuter_reaches_work_district() {
+        let mut passenger = commuter();
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+
+        assert!(passenger.alight((100, 0), true));
+        assert_eq!(passenger.journey(), Journey::Transferring { station: (100, 0) });
+        assert_eq!(passenger.route[0].station.position, (100, 0));
+
+        board_at(&mut passenger, (100, 0), Entity::from_raw(2));
+        // промежуточная остановка: едем дальше, маршрут не меняется
+        assert!(!passenger.alight((100, 100), true));
+        assert_eq!(passenger.route.len(), 3);
+
+        assert!(passenger.alight((100, 200), true));
+        assert_eq!(passenger.journey(), Journey::Arrived { district: WORK });
+        assert_eq!(passenger.settle(), Some(WORK));
+        assert_eq!(passenger.journey(), Journey::AtDistrict { district: WORK });
+        assert_eq!(passenger.last_visited_district, WORK);
+        assert!(passenger.route.is_empty());
+    }
+
+    #[test]
+    fn stop_on_a_later_leg_keeps_itinerary() {
+        let mut passenger = commuter();
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+
+        // первый поезд останавливается на станции, которая встретится только на второй линии
+        assert!(!passenger.alight((100, 100), true));
+        assert_eq!(passenger.route.len(), 4);
+        assert_eq!(passenger.journey(), Journey::Riding { train: Entity::from_raw(1) });
+    }
+
+    #[test]
+    fn infill_station_joins_route_on_its_line() {
+        let mut passenger = commuter();
+        // такой же перегон другой линии маршрут не затрагивает
+        passenger.insert_stop(0, (100, 200), (100, 100), (100, 150));
+        assert_eq!(passenger.route.len(), 4);
+
+        passenger.insert_stop(1, (100, 200), (100, 100), (100, 150));
+        assert_eq!(passenger.route.len(), 5);
+        assert_eq!(passenger.route[3].station.position, (100, 150));
+        assert_eq!(passenger.route[3].line, 1);
+
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+        assert!(passenger.alight((100, 0), true));
+        board_at(&mut passenger, (100, 0), Entity::from_raw(2));
+        assert!(!passenger.alight((100, 150), true));
+        assert!(passenger.alight((100, 200), true));
+        assert_eq!(passenger.journey(), Journey::Arrived { district: WORK });
+    }
+
+    #[test]
+    fn leaves_train_that_skips_alighting_station() {
+        let mut passenger = commuter();
+        passenger.route.remove(1);
+        passenger.route[0].line = 1;
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+
+        assert!(passenger.alight((100, 100), false));
+        assert_eq!(passenger.journey(), Journey::Transferring { station: (100, 100) });
+        assert_eq!(passenger.route.len(), 2);
+    }
+
+    #[test]
+    fn train_taken_off_the_line_drops_riders() {
+        let mut passenger = commuter();
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+        passenger.leave_train((0, 0));
+        assert_eq!(passenger.journey(), Journey::Transferring { station: (0, 0) });
+
+        // станции нет на пути - поездка отменяется, пассажир идёт домой
+        let mut passenger = commuter();
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+        passenger.leave_train((500, 500));
+        assert_eq!(passenger.journey(), Journey::Arrived { district: HOME });
+        assert_eq!(passenger.settle(), Some(HOME));
+    }
+
+    #[test]
+    fn invalid_transitions_are_rejected() {
+        let mut passenger = commuter();
+        assert!(!passenger.advance(Journey::Riding { train: Entity::from_raw(1) }));
+        assert!(!passenger.advance(Journey::Waiting { station: (0, 0) }));
+        assert_eq!(passenger.journey(), Journey::AtDistrict { district: HOME });
+
+        assert!(passenger.advance(Journey::WalkingToStation { station: (0, 0) }));
+        // встать ждать можно только на той станции, к которой шёл
+        assert!(!passenger.advance(Journey::Waiting { station: (100, 0) }));
+        assert!(!passenger.advance(Journey::AtDistrict { district: HOME }));
+    }
 
-    info!("want to go to: {}\t route length: {}\n", passenger.current_desire, passenger.route.len());
-}
\ No newline at end of file
+    // один и тот же город с одним сидом, но база пассажиров с другой раскладкой в HashMap
+    fn seeded_run(seed: u64, capacity: usize) -> Vec<[usize; 3]> {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut district_map = DistrictMap::default();
+        for (id, district_type) in [
+            DistrictType::Home,
+            DistrictType::Work,
+            DistrictType::Work,
+            DistrictType::Entertainment,
+            DistrictType::Entertainment,
+            DistrictType::Work,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut district = District::default();
+            district.id = id;
+            district.district_type = district_type;
+            district_map.districts.push(district);
+        }
+
+        let mut database = PassengerDatabase(HashMap::with_capacity(capacity));
+        for id in (0..200).rev() {
+            let mut passenger = commuter();
+            passenger.district_ids = [0, 0, 0];
+            database.0.insert(id, passenger);
+        }
+
+        let mut world = World::new();
+        world.insert_resource(district_map);
+        world.insert_resource(database);
+        world.insert_resource(GameRng::new(seed));
+        world.run_system_once(fill_passenger_pool).unwrap();
+
+        let database = world.resource::<PassengerDatabase>();
+        (0..200).map(|id| database.0[&id].district_ids).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_passengers() {
+        assert_eq!(seeded_run(42, 0), seeded_run(42, 4096));
+        assert_ne!(seeded_run(42, 0), seeded_run(43, 0));
+    }
+
+    #[test]
+    fn cancelled_trip_returns_home() {
+        let mut passenger = commuter();
+        passenger.cancel_trip();
+        assert_eq!(passenger.journey(), Journey::AtDistrict { district: HOME });
+
+        let mut passenger = commuter();
+        board_at(&mut passenger, (0, 0), Entity::from_raw(1));
+        passenger.cancel_trip();
+        assert_eq!(passenger.current_desire, PassengerDesire::Home);
+        assert_eq!(passenger.settle(), Some(HOME));
+    }
+}
@@ -0,0 +1,55 @@
+use bevy::{app::PluginGroupBuilder, prelude::*};
+use bevy_lunex::UiLunexPlugins;
+
+use crate::{
+    audio::AudioPlugin,
+    camera::CameraPlugin,
+    catchment::CatchmentPresentationPlugin,
+    cursor::CursorPlugin,
+    demolition::DemolitionPresentationPlugin,
+    depot::DepotPresentationPlugin,
+    district::DistrictPresentationPlugin,
+    line::MetroLinePresentationPlugin,
+    metro::MetroPresentationPlugin,
+    save::SavePlugin,
+    settings::SettingsPlugin,
+    signalling::SignallingPresentationPlugin,
+    station::StationPresentationPlugin,
+    station_blueprint::StationBlueprintPlugin,
+    train::TrainPresentationPlugin,
+    ui::{AudioUIPlugin, ClockUIPlugin, MainMenuPlugin, MoneyUIPlugin, NotificationUIPlugin, SettingsUIPlugin, StationUIPlugin, TrainUIPlugin, TutorialUIPlugin},
+};
+
+/// Окно, ввод, отрисовка, интерфейс и звук поверх `SimulationPlugins`.
+pub struct PresentationPlugins;
+
+impl PluginGroup for PresentationPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add_group(UiLunexPlugins)
+            .add(CursorPlugin)
+            .add(CameraPlugin)
+            .add(MetroPresentationPlugin)
+            .add(MetroLinePresentationPlugin)
+            .add(StationPresentationPlugin)
+            .add(StationBlueprintPlugin)
+            .add(DemolitionPresentationPlugin)
+            .add(TrainPresentationPlugin)
+            .add(SignallingPresentationPlugin)
+            .add(DepotPresentationPlugin)
+            .add(DistrictPresentationPlugin)
+            .add(CatchmentPresentationPlugin)
+            .add(AudioPlugin)
+            .add(MainMenuPlugin)
+            .add(StationUIPlugin)
+            .add(TutorialUIPlugin)
+            .add(MoneyUIPlugin)
+            .add(ClockUIPlugin)
+            .add(NotificationUIPlugin)
+            .add(TrainUIPlugin)
+            .add(AudioUIPlugin)
+            .add(SettingsUIPlugin)
+            .add(SettingsPlugin)
+            .add(SavePlugin)
+    }
+}
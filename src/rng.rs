@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+
+// у звука свой поток, чтобы музыка не сдвигала случайность города
+const AUDIO_STREAM: u64 = 1;
+
+pub struct RngPlugin {
+    /// Сид из командной строки; его можно сменить в главном меню, иначе каждая новая игра получает случайный.
+    pub seed: Option<u64>,
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        info!("Seed: {seed}");
+
+        app.insert_resource(NewGameSeed(self.seed))
+            .insert_resource(GameRng::new(seed));
+        app.add_systems(OnExit(GameState::MainMenu), reseed_new_game);
+    }
+}
+
+/// Сид следующей новой игры, None - случайный.
+#[derive(Resource)]
+pub struct NewGameSeed(pub Option<u64>);
+
+/// Единственный источник случайности игры: одинаковый сид и одинаковые действия игрока
+/// дают один и тот же город и одних и тех же пассажиров.
+#[derive(Resource, Clone, Deref, DerefMut, Serialize, Deserialize)]
+pub struct GameRng {
+    pub seed: u64,
+    #[deref]
+    simulation: ChaCha8Rng,
+    audio: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        let mut audio = ChaCha8Rng::seed_from_u64(seed);
+        audio.set_stream(AUDIO_STREAM);
+
+        Self {
+            seed,
+            simulation: ChaCha8Rng::seed_from_u64(seed),
+            audio,
+        }
+    }
+
+    pub fn audio(&mut self) -> &mut ChaCha8Rng {
+        &mut self.audio
+    }
+}
+
+// загрузка сохранения потом перезапишет генератор сохранённым состоянием
+fn reseed_new_game(mut commands: Commands, new_game_seed: Res<NewGameSeed>) {
+    let seed = new_game_seed.0.unwrap_or_else(rand::random);
+    info!("Seed: {seed}");
+    commands.insert_resource(GameRng::new(seed));
+}
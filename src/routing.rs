@@ -0,0 +1,265 @@
+use std::collections::BinaryHeap;
+
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    line::ServicePattern,
+    metro::Metro,
+    station::Station,
+    train::{TrainModel, TRAIN_SPEED, TRAIN_STOP_TIME_SECS},
+    utils::graph::MinCost,
+};
+
+// штраф за пересадку сверх ожидания поезда другой линии, в секундах
+pub const TRANSFER_PENALTY_SECS: f32 = 15.0;
+// на другую линию пересадочной станции ещё нужно перейти по переходу, а поезд другой схемы
+// той же линии ждут на той же платформе
+pub const INTERCHANGE_WALK_SECS: f32 = 5.0;
+
+/// Шаг маршрута пассажира. `line` - линия, на которой пассажир уезжает со станции
+/// (у последнего шага - линия, на которой он приехал).
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RouteStep {
+    pub station: Station,
+    pub line: usize,
+    /// На этой станции пассажир выходит и пересаживается на другую линию.
+    pub transfer: bool,
+}
+
+/// Линия и схема остановок, по которой ходят её поезда.
+pub type Service = (usize, ServicePattern);
+
+/// Станция, на которой пассажир выйдет из поезда, в который садится на первом шаге маршрута.
+pub fn alighting_station(route: &[RouteStep]) -> Option<(i32, i32)> {
+    let rest = route.get(1..)?;
+    rest.iter()
+        .find(|step| step.transfer)
+        .or(rest.last())
+        .map(|step| step.station.position)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct RouteState {
+    position: (i32, i32),
+    // на каком поезде пассажир сюда приехал
+    service: Option<Service>,
+}
+
+// среднее ожидание поезда линии - половина интервала между поездами той же схемы
+fn expected_wait(metro: &Metro, line_id: usize, trains: usize) -> Option<f32> {
+    if trains == 0 {
+        return None;
+    }
+
+    // складываем в одном порядке, иначе порядок HashMap меняет сумму в последних знаках
+    let mut segments: Vec<f32> = metro
+        .stations
+        .edges()
+        .filter(|(_, edge)| edge.data.line == line_id)
+        .map(|(_, edge)| edge.weight)
+        .collect();
+    segments.sort_by(f32::total_cmp);
+    let length: f32 = segments.iter().sum();
+
+    // по обычной линии поезд ходит туда и обратно, по кольцу - только по кругу
+    let laps = if metro.lines[line_id].is_ring { 1. } else { 2. };
+    let headway = laps * length / TRAIN_SPEED / trains as f32;
+    Some(headway / 2.)
+}
+
+// поезд этой схемы останавливается на станции, значит на нём можно сесть или выйти
+fn stops_at(metro: &Metro, (line_id, pattern): Service, position: (i32, i32)) -> bool {
+    metro.lines[line_id].stops_at(pattern, position)
+}
+
+/// Самый быстрый маршрут от любой из станций `origins` до любой из `targets` по графу
+/// станций. У каждой станции отправления - сколько секунд до неё идти пешком.
+/// Учитывает время в пути, остановки, ожидание поезда и пересадки.
+/// Садиться и выходить можно только там, где поезд выбранной схемы останавливается.
+/// Маршрут всегда содержит хотя бы один перегон.
+pub fn find_route(
+    metro: &Metro,
+    origins: &[((i32, i32), f32)],
+    targets: &[(i32, i32)],
+    trains_per_service: &HashMap<Service, usize>,
+) -> Option<Vec<RouteStep>> {
+    // обходим схемы в одном порядке, чтобы маршрут не зависел от порядка HashMap
+    let mut waits: Vec<(Service, f32)> = trains_per_service
+        .iter()
+        .filter_map(|(service, trains)| Some((*service, expected_wait(metro, service.0, *trains)?)))
+        .collect();
+    waits.sort_by_key(|(service, _)| (service.0, service.1 as usize));
+
+    let mut costs: HashMap<RouteState, f32> = HashMap::new();
+    let mut previous: HashMap<RouteState, RouteState> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+
+    for (origin, walk_secs) in origins.iter() {
+        let state = RouteState {
+            position: *origin,
+            service: None,
+        };
+        costs.insert(state, *walk_secs);
+        queue.push(MinCost { cost: *walk_secs, state });
+    }
+
+    let mut destination = None;
+    while let Some(MinCost { cost, state }) = queue.pop() {
+        if cost > *costs.get(&state).unwrap_or(&f32::INFINITY) {
+            continue;
+        }
+
+        // выйти можно только там, где поезд останавливается
+        let can_leave = state
+            .service
+            .is_some_and(|service| stops_at(metro, service, state.position));
+        if can_leave && targets.contains(&state.position) {
+            destination = Some(state);
+            break;
+        }
+
+        for edge in metro.stations.neighbours(state.position) {
+            // едем дальше тем же поездом или садимся на поезд линии этого перегона
+            let mut options: Vec<(Service, f32)> = vec![];
+            if let Some(service) = state.service.filter(|service| service.0 == edge.data.line) {
+                options.push((service, 0.));
+            }
+            if state.service.is_none() || can_leave {
+                for (service, wait) in waits.iter() {
+                    if service.0 != edge.data.line
+                        || state.service == Some(*service)
+                        || !stops_at(metro, *service, state.position)
+                    {
+                        continue;
+                    }
+                    let penalty = match state.service {
+                        Some((line_id, _)) if line_id != service.0 => {
+                            TRANSFER_PENALTY_SECS + INTERCHANGE_WALK_SECS
+                        }
+                        Some(_) => TRANSFER_PENALTY_SECS,
+                        None => 0.,
+                    };
+                    options.push((*service, wait + penalty));
+                }
+            }
+
+            for (service, boarding_cost) in options {
+                let mut next_cost = cost + boarding_cost + edge.weight / TRAIN_SPEED;
+                // станции, которые поезд проезжает, не отнимают времени ни на стоянку, ни на торможение с разгоном
+                if stops_at(metro, service, edge.to) {
+                    next_cost += TRAIN_STOP_TIME_SECS + TrainModel::Standard.stop_time_loss_secs();
+                }
+
+                let next = RouteState {
+                    position: edge.to,
+                    service: Some(service),
+                };
+                if next_cost < *costs.get(&next).unwrap_or(&f32::INFINITY) {
+                    costs.insert(next, next_cost);
+                    previous.insert(next, state);
+                    queue.push(MinCost {
+                        cost: next_cost,
+                        state: next,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut path = vec![destination?];
+    while let Some(state) = previous.get(path.last().unwrap()) {
+        path.push(*state);
+    }
+    path.reverse();
+
+    // у каждой станции пути - линия, на которой с неё уезжают.
+    // пересадка и на другой поезд той же линии, если у него другая схема
+    let mut route = vec![];
+    for i in 0..path.len() {
+        let service = match path.get(i + 1) {
+            Some(next) => next.service.unwrap(),
+            None => path[i].service.unwrap(),
+        };
+        let transfer = i > 0 && i + 1 < path.len() && path[i].service != Some(service);
+
+        route.push(RouteStep {
+            station: Station::new(path[i].position),
+            line: service.0,
+            transfer,
+        });
+    }
+
+    Some(route)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    const S: (i32, i32) = (0, 0);
+    const M: (i32, i32) = (100, 0);
+    const T: (i32, i32) = (200, 0);
+
+    fn metro(lines: &[Vec<(i32, i32)>]) -> Metro {
+        let mut metro = Metro::default();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for points in lines {
+            metro.add_line(points.clone(), &mut rng);
+        }
+        metro
+    }
+
+    // по поезду на каждой линии, все останавливаются везде
+    fn one_train_each(metro: &Metro) -> HashMap<Service, usize> {
+        (0..metro.lines.len())
+            .map(|line_id| ((line_id, ServicePattern::AllStops), 1))
+            .collect()
+    }
+
+    fn stations(route: &[RouteStep]) -> Vec<(i32, i32)> {
+        route.iter().map(|step| step.station.position).collect()
+    }
+
+    #[test]
+    fn direct_ride_along_one_line() {
+        let metro = metro(&[vec![S, M, T]]);
+        let route = find_route(&metro, &[(S, 0.)], &[T], &one_train_each(&metro)).unwrap();
+
+        assert_eq!(stations(&route), vec![S, M, T]);
+        assert!(route.iter().all(|step| step.line == 0 && !step.transfer));
+        assert_eq!(alighting_station(&route), Some(T));
+    }
+
+    #[test]
+    fn transfer_only_when_it_saves_time() {
+        // короткие линии с пересадкой в M против длинного объезда без пересадки
+        let metro_with_detour = metro(&[vec![S, M], vec![M, T], vec![S, (0, 3000), T]]);
+        let route = find_route(&metro_with_detour, &[(S, 0.)], &[T], &one_train_each(&metro_with_detour)).unwrap();
+        assert_eq!(stations(&route), vec![S, M, T]);
+        assert!(route[1].transfer);
+        assert_eq!((route[0].line, route[1].line), (0, 1));
+        assert_eq!(alighting_station(&route), Some(M));
+
+        // объезд чуть длиннее - штраф за пересадку того не стоит
+        let metro_with_bypass = metro(&[vec![S, M], vec![M, T], vec![S, (100, 50), T]]);
+        let route = find_route(&metro_with_bypass, &[(S, 0.)], &[T], &one_train_each(&metro_with_bypass)).unwrap();
+        assert_eq!(stations(&route), vec![S, (100, 50), T]);
+        assert!(route.iter().all(|step| step.line == 2 && !step.transfer));
+    }
+
+    #[test]
+    fn unreachable_destination_has_no_route() {
+        // до T ходит только линия без поездов
+        let metro_without_trains = metro(&[vec![S, M], vec![M, T]]);
+        let trains = HashMap::from_iter([((0, ServicePattern::AllStops), 1)]);
+        assert!(find_route(&metro_without_trains, &[(S, 0.)], &[T], &trains).is_none());
+
+        // станция на отдельной линии, с остальными не связанной
+        let metro_apart = metro(&[vec![S, M], vec![(500, 500), (600, 500)]]);
+        assert!(find_route(&metro_apart, &[(S, 0.)], &[(500, 500)], &one_train_each(&metro_apart)).is_none());
+    }
+}
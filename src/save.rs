@@ -0,0 +1,364 @@
+use std::{fs, time::Duration};
+
+use bevy::{ecs::system::SystemParam, prelude::*, time::common_conditions::on_timer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    city_events::OutOfService,
+    clock::GameClock,
+    depot::InDepot,
+    district::DistrictMap,
+    line::{MetroLine, SpawnLineCurveEvent},
+    metro::Metro,
+    money::{Finances, Money},
+    passenger::{Journey, PassengerDatabase},
+    rng::GameRng,
+    signalling::Signals,
+    station::{SpawnStationEvent, Station, StationButton},
+    train::{SpawnTrainEvent, Train, TrainOrder},
+    ui::MoneyRedrawEvent,
+    GameState,
+};
+
+pub const SAVE_FILE: &str = "save.ron";
+const AUTOSAVE_INTERVAL_SECS: u64 = 60;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>();
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            load_game.run_if(resource_exists::<PendingLoad>),
+        );
+        app.add_systems(
+            Update,
+            (
+                save_hotkey,
+                autosave.run_if(on_timer(Duration::from_secs(AUTOSAVE_INTERVAL_SECS))),
+                write_save,
+                apply_pending_load.run_if(resource_exists::<PendingLoad>),
+            )
+                .chain()
+                .run_if(in_state(GameState::InGame)),
+        );
+        app.add_systems(Update, read_save.run_if(in_state(GameState::MainMenu)));
+    }
+}
+
+#[derive(Event)]
+pub struct SaveGameEvent;
+
+#[derive(Event)]
+pub struct LoadGameEvent;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StationSave {
+    station: Station,
+    button: StationButton,
+    #[serde(default)] // станцию закрыло городское событие
+    out_of_service: Option<OutOfService>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct TrainSave {
+    train: Train,
+    translation: Vec3,
+    rotation: Quat,
+    // приказ, поломка и обслуживание в депо переживают загрузку
+    #[serde(default)]
+    order: Option<TrainOrder>,
+    #[serde(default)]
+    out_of_service: Option<OutOfService>,
+    #[serde(default)]
+    in_depot: bool,
+}
+
+// всё состояние партии, которое нужно, чтобы восстановить игру после перезапуска
+#[derive(Clone, Serialize, Deserialize)]
+struct GameSave {
+    money: i64,
+    #[serde(default)]
+    finances: Finances,
+    lines: Vec<MetroLine>,
+    stations: Vec<StationSave>,
+    trains: Vec<TrainSave>,
+    districts: DistrictMap,
+    passengers: PassengerDatabase,
+    rng: GameRng,
+    #[serde(default)]
+    clock: GameClock,
+}
+
+// сохранение, которое ждёт, пока события спавна создадут станции и поезда
+#[derive(Resource)]
+pub struct PendingLoad {
+    save: GameSave,
+    // сколько поездов отправлено на спавн; поезда линий без пути не спавнятся
+    trains_sent: usize,
+}
+
+fn save_hotkey(keyboard: Res<ButtonInput<KeyCode>>, mut ev_save: EventWriter<SaveGameEvent>) {
+    if keyboard.just_pressed(KeyCode::F5) {
+        ev_save.send(SaveGameEvent);
+    }
+}
+
+fn autosave(mut ev_save: EventWriter<SaveGameEvent>) {
+    ev_save.send(SaveGameEvent);
+}
+
+// ресурсы, которые целиком попадают в сохранение
+#[derive(SystemParam)]
+struct SavedResources<'w> {
+    metro: Res<'w, Metro>,
+    money: Res<'w, Money>,
+    finances: Res<'w, Finances>,
+    district_map: Res<'w, DistrictMap>,
+    passenger_database: Res<'w, PassengerDatabase>,
+    rng: Res<'w, GameRng>,
+    clock: Res<'w, GameClock>,
+}
+
+type SavedTrain<'a> = (
+    &'a Train,
+    &'a Transform,
+    Option<&'a TrainOrder>,
+    Option<&'a OutOfService>,
+    Has<InDepot>,
+);
+
+fn write_save(
+    mut ev_save: EventReader<SaveGameEvent>,
+    resources: SavedResources,
+    q_station: Query<(&Station, &StationButton, Option<&OutOfService>)>,
+    q_train: Query<SavedTrain>,
+) {
+    // несколько запросов за кадр сохраняем одним файлом
+    if ev_save.read().count() == 0 {
+        return;
+    }
+
+    let save = GameSave {
+        money: resources.money.0,
+        finances: *resources.finances,
+        lines: resources.metro.lines.clone(),
+        stations: q_station
+            .iter()
+            .map(|(station, button, out_of_service)| StationSave {
+                station: *station,
+                button: button.clone(),
+                out_of_service: out_of_service.copied(),
+            })
+            .collect(),
+        trains: q_train
+            .iter()
+            .map(|(train, transform, order, out_of_service, in_depot)| TrainSave {
+                train: train.clone(),
+                translation: transform.translation,
+                rotation: transform.rotation,
+                order: order.copied(),
+                out_of_service: out_of_service.copied(),
+                in_depot,
+            })
+            .collect(),
+        districts: resources.district_map.clone(),
+        passengers: resources.passenger_database.clone(),
+        rng: resources.rng.clone(),
+        clock: *resources.clock,
+    };
+
+    let serialized = match ron::ser::to_string_pretty(&save, ron::ser::PrettyConfig::default()) {
+        Ok(serialized) => serialized,
+        Err(e) => {
+            error!("Failed to serialize save: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = fs::write(SAVE_FILE, serialized) {
+        error!("Failed to write {SAVE_FILE}: {e}");
+        return;
+    }
+    info!("Game saved to {SAVE_FILE}");
+}
+
+fn read_save(
+    mut commands: Commands,
+    mut ev_load: EventReader<LoadGameEvent>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for _ in ev_load.read() {
+        let contents = match fs::read_to_string(SAVE_FILE) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("No save to load from {SAVE_FILE}: {e}");
+                continue;
+            }
+        };
+
+        let save: GameSave = match ron::from_str(&contents) {
+            Ok(save) => save,
+            Err(e) => {
+                error!("Corrupted save {SAVE_FILE}: {e}");
+                continue;
+            }
+        };
+
+        commands.insert_resource(PendingLoad { save, trains_sent: 0 });
+        next_state.set(GameState::InGame);
+    }
+}
+
+fn load_game(
+    mut commands: Commands,
+    mut pending: ResMut<PendingLoad>,
+    mut metro: ResMut<Metro>,
+    mut ev_spawn_line: EventWriter<SpawnLineCurveEvent>,
+    mut ev_spawn_station: EventWriter<SpawnStationEvent>,
+    mut ev_spawn_train: EventWriter<SpawnTrainEvent>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    let save = &pending.save;
+
+    commands.insert_resource(Money(save.money));
+    commands.insert_resource(save.finances);
+    commands.insert_resource(save.districts.clone());
+    commands.insert_resource(save.passengers.clone());
+    commands.insert_resource(save.rng.clone());
+    commands.insert_resource(save.clock);
+    redraw_money.send(MoneyRedrawEvent);
+
+    // от прошлой партии в графе не должно остаться ни станций, ни перегонов
+    *metro = Metro::default();
+    metro.lines = save.lines.clone();
+    for line_id in 0..metro.lines.len() {
+        metro.lines[line_id].fill_skip_groups();
+        metro.lines[line_id].update_curve();
+        metro.update_line_edges(line_id);
+        ev_spawn_line.send(SpawnLineCurveEvent { line_id });
+    }
+
+    for station_save in save.stations.iter() {
+        // цену станции вместе с остальным вернёт сохранённая `StationButton`
+        ev_spawn_station.send(SpawnStationEvent {
+            position: station_save.station.position,
+            cost: 0,
+        });
+    }
+
+    let mut trains_sent = 0;
+    for train_save in save.trains.iter() {
+        let line = &metro.lines[train_save.train.line];
+        let (Some(first_station), Some(_)) = (line.stations.front(), &line.path) else {
+            continue;
+        };
+        trains_sent += 1;
+        ev_spawn_train.send(SpawnTrainEvent {
+            line: train_save.train.line,
+            station: first_station.position,
+            model: train_save.train.model,
+            pattern: train_save.train.pattern,
+            cost: 0,
+        });
+    }
+    pending.trains_sent = trains_sent;
+}
+
+// как только станции и поезда заспавнены, возвращаем им сохранённое состояние
+fn apply_pending_load(
+    mut commands: Commands,
+    pending: Res<PendingLoad>,
+    mut q_station: Query<(Entity, &Station, &mut StationButton)>,
+    mut q_train: Query<(Entity, &mut Train, &mut Transform)>,
+    mut passengers: ResMut<PassengerDatabase>,
+    district_map: Res<DistrictMap>,
+    mut signals: ResMut<Signals>,
+) {
+    let save = &pending.save;
+    if q_station.iter().len() < save.stations.len() || q_train.iter().len() < pending.trains_sent {
+        return;
+    }
+
+    for (e_station, station, mut button) in q_station.iter_mut() {
+        let Some(station_save) = save
+            .stations
+            .iter()
+            .find(|saved| saved.station.position == station.position)
+        else {
+            continue;
+        };
+        *button = station_save.button.clone();
+        if let Some(out_of_service) = station_save.out_of_service {
+            commands.entity(e_station).insert(out_of_service);
+        }
+    }
+
+    // только что купленные поезда одной линии неотличимы, поэтому раздаём сохранения по линиям
+    let mut restored: Vec<bool> = vec![false; save.trains.len()];
+    for (e_train, mut train, mut transform) in q_train.iter_mut() {
+        let Some(index) = save
+            .trains
+            .iter()
+            .enumerate()
+            .position(|(i, saved)| !restored[i] && saved.train.line == train.line)
+        else {
+            continue;
+        };
+        restored[index] = true;
+
+        let train_save = &save.trains[index];
+        *train = train_save.train.clone();
+        transform.translation = train_save.translation;
+        transform.rotation = train_save.rotation;
+        // перегон у первой станции поезд занял при появлении, а сохранён он мог быть где угодно
+        signals.release(e_train);
+
+        let mut e_train = commands.entity(e_train);
+        if let Some(order) = train_save.order {
+            e_train.insert(order);
+        }
+        if let Some(out_of_service) = train_save.out_of_service {
+            e_train.insert(out_of_service);
+        }
+        if train_save.in_depot {
+            e_train.insert(InDepot);
+        }
+    }
+
+    // у поездов теперь другие сущности, а в старых сохранениях состояния поездки нет вовсе,
+    // поэтому восстанавливаем его по тем, кто где числится
+    let mut rebind = |id: &usize, journey: Journey| {
+        if let Some(passenger) = passengers.0.get_mut(id) {
+            passenger.rebind(journey);
+        }
+    };
+    for district in district_map.districts.iter() {
+        let journey = Journey::AtDistrict { district: district.id };
+        district.passenger_ids.iter().for_each(|id| rebind(id, journey));
+    }
+    for (_, station, button) in q_station.iter() {
+        let journey = Journey::Waiting { station: station.position };
+        button.passenger_ids.iter().for_each(|id| rebind(id, journey));
+    }
+    for (e_train, train, _) in q_train.iter() {
+        let journey = Journey::Riding { train: e_train };
+        train.passenger_ids().iter().for_each(|id| rebind(id, journey));
+    }
+    // поезда линий без пути не восстановились, их пассажиры возвращаются в свои районы
+    for (index, train_save) in save.trains.iter().enumerate() {
+        if restored[index] {
+            continue;
+        }
+        for id in train_save.train.passenger_ids() {
+            if let Some(passenger) = passengers.0.get_mut(id) {
+                passenger.rebind_cancelled();
+            }
+        }
+    }
+
+    commands.remove_resource::<PendingLoad>();
+    info!("Game loaded from {SAVE_FILE}");
+}
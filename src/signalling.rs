@@ -0,0 +1,124 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    line::{LinePath, MetroLine},
+    metro::{Direction, Metro},
+    train::Train,
+};
+
+pub struct SignallingPlugin;
+
+impl Plugin for SignallingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Signals>();
+    }
+}
+
+// красный огонёк над поездом, который ждёт, пока освободится перегон
+pub struct SignallingPresentationPlugin;
+
+impl Plugin for SignallingPresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (attach_signal_lamp, update_signal_lamp));
+    }
+}
+
+/// Перегон между соседними станциями в одну сторону. Линии, которые идут между
+/// одними и теми же станциями, делят общий перегон.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Block {
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+}
+
+/// Какой поезд сейчас занимает перегон. В одном перегоне может быть только один поезд.
+#[derive(Resource, Default)]
+pub struct Signals(HashMap<Block, Entity>);
+
+impl Signals {
+    pub fn is_free_for(&self, block: Block, train: Entity) -> bool {
+        self.0.get(&block).is_none_or(|holder| *holder == train)
+    }
+
+    pub fn reserve(&mut self, block: Block, train: Entity) {
+        self.release(train);
+        self.0.insert(block, train);
+    }
+
+    // поезд доехал до станции и освободил перегон за собой
+    pub fn release(&mut self, train: Entity) {
+        self.0.retain(|_, holder| *holder != train);
+    }
+
+    // проданные и снесённые поезда не должны держать перегоны
+    pub fn forget_missing(&mut self, exists: impl Fn(Entity) -> bool) {
+        self.0.retain(|_, holder| exists(*holder));
+    }
+}
+
+/// Поезд стоит на станции перед красным сигналом.
+#[derive(Component)]
+pub struct AtRedSignal;
+
+// перегон, в который поезд въедет со станции на отметке `distance`.
+// None, если поезд не на станции. На конечной обычной линии поезд развернётся
+pub fn block_ahead(
+    metro: &Metro,
+    line: &MetroLine,
+    path: &LinePath,
+    distance: f32,
+    direction: Direction,
+) -> Option<Block> {
+    let here = path
+        .station_offsets()
+        .iter()
+        .position(|offset| (offset - path.wrap(distance)).abs() < 0.5)?;
+    let from = line.stations.iter().nth(here)?.position;
+
+    let to = metro.next_station(line.id, from, direction).or_else(|| match line.is_ring {
+        true => None,
+        false => metro.next_station(line.id, from, direction.reversed()),
+    })?;
+
+    Some(Block { from, to })
+}
+
+#[derive(Component)]
+struct SignalLamp;
+
+fn attach_signal_lamp(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    q_train: Query<Entity, Added<Train>>,
+) {
+    for e_train in q_train.iter() {
+        commands.entity(e_train).with_child((
+            Mesh2d(meshes.add(Circle::new(5.))),
+            MeshMaterial2d(materials.add(Color::srgb(0.9, 0.1, 0.1))),
+            Transform::from_xyz(0., 14., 1.),
+            Visibility::Hidden,
+            SignalLamp,
+        ));
+    }
+}
+
+fn update_signal_lamp(
+    q_train: Query<(&Children, Has<AtRedSignal>), With<Train>>,
+    mut q_lamp: Query<&mut Visibility, With<SignalLamp>>,
+) {
+    for (children, at_red_signal) in q_train.iter() {
+        for child in children.iter() {
+            let Ok(mut visibility) = q_lamp.get_mut(*child) else {
+                continue;
+            };
+            let wanted = match at_red_signal {
+                true => Visibility::Inherited,
+                false => Visibility::Hidden,
+            };
+            if *visibility != wanted {
+                *visibility = wanted;
+            }
+        }
+    }
+}
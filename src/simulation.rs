@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use bevy::{app::PluginGroupBuilder, ecs::system::SystemParam, prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+    catchment::CatchmentPlugin,
+    city_events::CityEventsPlugin,
+    clock::{ClockPlugin, GameClock},
+    demolition::DemolitionPlugin,
+    depot::DepotPlugin,
+    district::{DistrictMap, DistrictPlugin},
+    line::MetroLinePlugin,
+    map_generation::MapGenerationPlugin,
+    metro::{Metro, MetroPlugin},
+    money::{Money, MoneyPlugin},
+    passenger::{PassengerDatabase, PassengerPlugin},
+    rng::RngPlugin,
+    signalling::SignallingPlugin,
+    station::StationPlugin,
+    telemetry::TelemetryPlugin,
+    train::TrainPlugin,
+    GameState,
+};
+
+// один кадр безоконной симуляции, время идёт не по часам, а шагами
+const HEADLESS_FRAME_SECS: f64 = 1. / 60.;
+
+/// Порядок шагов симуляции внутри кадра. Без него планировщик каждый раз
+/// выбирает свой порядок систем и одинаковый сид даёт разные партии.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SimulationSet {
+    Demolish,
+    Build,
+    Trains,
+    City,
+    Passengers,
+    Economy,
+}
+
+struct SimulationSchedulePlugin;
+
+impl Plugin for SimulationSchedulePlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(
+            Update,
+            (
+                SimulationSet::Demolish,
+                SimulationSet::Build,
+                SimulationSet::Trains,
+                SimulationSet::City,
+                SimulationSet::Passengers,
+                SimulationSet::Economy,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Вся игровая логика без окна, ассетов и звука: работает поверх `MinimalPlugins`.
+pub struct SimulationPlugins {
+    pub seed: Option<u64>,
+}
+
+impl PluginGroup for SimulationPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(SimulationSchedulePlugin)
+            .add(RngPlugin { seed: self.seed })
+            .add(ClockPlugin)
+            .add(MetroPlugin)
+            .add(MetroLinePlugin)
+            .add(StationPlugin)
+            .add(DemolitionPlugin)
+            .add(TrainPlugin)
+            .add(SignallingPlugin)
+            .add(DepotPlugin)
+            .add(TelemetryPlugin)
+            .add(DistrictPlugin)
+            .add(CatchmentPlugin)
+            .add(PassengerPlugin)
+            .add(MoneyPlugin)
+            .add(CityEventsPlugin)
+            .add(MapGenerationPlugin)
+    }
+}
+
+/// Гоняет симуляцию заданное число игровых минут так быстро, как может, и выходит.
+pub struct HeadlessPlugin {
+    pub minutes: u64,
+}
+
+#[derive(Resource)]
+struct HeadlessRun {
+    minutes: f64,
+    // показания игровых часов на первом кадре партии
+    started_at: Option<f64>,
+}
+
+impl HeadlessRun {
+    fn elapsed_minutes(&self, clock: &GameClock) -> f64 {
+        self.started_at.map_or(0., |start| clock.total_minutes() - start)
+    }
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+            HEADLESS_FRAME_SECS,
+        )))
+        .insert_resource(HeadlessRun {
+            minutes: self.minutes as f64,
+            started_at: None,
+        });
+        app.add_systems(Update, finish_headless_run.run_if(in_state(GameState::InGame)));
+        app.add_systems(OnExit(GameState::InGame), headless_game_over);
+    }
+}
+
+// итог партии для лога безоконного запуска
+#[derive(SystemParam)]
+struct Report<'w> {
+    metro: Res<'w, Metro>,
+    money: Res<'w, Money>,
+    district_map: Res<'w, DistrictMap>,
+    database: Res<'w, PassengerDatabase>,
+    clock: Res<'w, GameClock>,
+}
+
+impl Report<'_> {
+    fn log(&self) {
+        let station_count: usize = self.metro.lines.iter().map(|line| line.stations.len()).sum();
+        info!(
+            "{}, lines: {}, stations: {}, districts: {}, passengers: {}, money: {}",
+            *self.clock,
+            self.metro.lines.len(),
+            station_count,
+            self.district_map.districts.len(),
+            self.database.0.len(),
+            self.money.0
+        );
+    }
+}
+
+fn finish_headless_run(
+    mut run: ResMut<HeadlessRun>,
+    report: Report,
+    mut exit: EventWriter<AppExit>,
+) {
+    run.started_at.get_or_insert(report.clock.total_minutes());
+    let elapsed = run.elapsed_minutes(&report.clock);
+    if elapsed < run.minutes {
+        return;
+    }
+
+    info!("Simulated {elapsed:.0} minutes");
+    report.log();
+    exit.send(AppExit::Success);
+}
+
+// из игры в безоконном режиме выходят только проигрышем
+fn headless_game_over(run: Res<HeadlessRun>, report: Report, mut exit: EventWriter<AppExit>) {
+    error!("Game over after {:.1} simulated minutes", run.elapsed_minutes(&report.clock));
+    report.log();
+    exit.send(AppExit::error());
+}
@@ -1,13 +1,13 @@
 use std::{f32::consts::PI, time::Duration, usize};
 
 use bevy::prelude::*;
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    camera::MainCamera, cursor::CursorPosition, line::{SpawnLineCurveEvent, UpdateLineRendererEvent}, metro::{Direction, Metro}, money::Money, station_blueprint::{SetBlueprintColorEvent, StationBlueprint}, train::SpawnTrainEvent, ui::{BuildingLineTutorial, MoneyRedrawEvent, ProlongLineTutorial}, GameState
+    cursor::CursorPosition, rng::GameRng, utils::naming::unique_name, simulation::SimulationSet, line::{ServicePattern, SpawnLineCurveEvent, UpdateLineRendererEvent}, metro::{Direction, Metro}, money::Money, passenger::PassengerDatabase, station_blueprint::{SetBlueprintColorEvent, StationBlueprint}, train::{SpawnTrainEvent, TrainModel}, ui::{BuildingLineTutorial, MoneyRedrawEvent, ProlongLineTutorial}, GameState
 };
 
-pub const STATION_NAMES: [&str; 11] = [
+pub const STATION_NAMES: [&str; 67] = [
     "Достоевская",
     "Обводный канал",
     "Озерки",
@@ -19,10 +19,80 @@ pub const STATION_NAMES: [&str; 11] = [
     "Дыбенко",
     "Звездная",
     "Рыбацкое",
+    "Девяткино",
+    "Гражданский проспект",
+    "Академическая",
+    "Политехническая",
+    "Площадь Мужества",
+    "Лесная",
+    "Выборгская",
+    "Площадь Ленина",
+    "Чернышевская",
+    "Площадь Восстания",
+    "Владимирская",
+    "Пушкинская",
+    "Технологический институт",
+    "Балтийская",
+    "Нарвская",
+    "Кировский завод",
+    "Ленинский проспект",
+    "Проспект Ветеранов",
+    "Проспект Просвещения",
+    "Удельная",
+    "Пионерская",
+    "Черная речка",
+    "Петроградская",
+    "Горьковская",
+    "Невский проспект",
+    "Фрунзенская",
+    "Московские ворота",
+    "Электросила",
+    "Парк Победы",
+    "Московская",
+    "Приморская",
+    "Василеостровская",
+    "Гостиный двор",
+    "Маяковская",
+    "Площадь Александра Невского",
+    "Елизаровская",
+    "Ломоносовская",
+    "Пролетарская",
+    "Обухово",
+    "Спасская",
+    "Лиговский проспект",
+    "Новочеркасская",
+    "Ладожская",
+    "Проспект Большевиков",
+    "Комендантский проспект",
+    "Старая Деревня",
+    "Крестовский остров",
+    "Чкаловская",
+    "Спортивная",
+    "Адмиралтейская",
+    "Садовая",
+    "Звенигородская",
+    "Волковская",
+    "Бухарестская",
+    "Международная",
+    "Беговая",
 ];
 
-const STATION_COST: u32 = 100;
-pub const STATION_MAX_PASSENGERS: u32 = 12;
+pub const STATION_COST: i64 = 100;
+// платформа новой линии на уже построенной станции
+pub const INTERCHANGE_COST: i64 = 50;
+// ближе этого к станции другой линии чертёж цепляется к ней, а не ставит новую станцию рядом
+const JOIN_RADIUS: f32 = 40.;
+// клик ближе этого к пути линии врезает в перегон новую станцию
+const SEGMENT_CLICK_RADIUS: f32 = 12.;
+// вместимость станции по уровням: сначала добавляются платформы, потом расширяется вестибюль
+pub const STATION_CAPACITY_LEVELS: [usize; 4] = [12, 18, 26, 36];
+// цена перехода на следующий уровень
+pub const STATION_UPGRADE_COSTS: [i64; 3] = [120, 200, 320];
+// радиус, из которого к станции приходят пешком, и цена его расширения
+pub const CATCHMENT_RADII: [f32; 3] = [60., 90., 120.];
+pub const CATCHMENT_UPGRADE_COSTS: [i64; 2] = [80, 160];
+// сколько станция базового уровня терпит давку, большие - пропорционально дольше
+const OVERCROWDING_SECS: f32 = 20.;
 
 pub struct StationPlugin;
 
@@ -30,25 +100,49 @@ impl Plugin for StationPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnStationEvent>()
             .add_event::<BuildStationEvent>()
-            .add_event::<StartBuildingEvent>();
+            .add_event::<CloseRingEvent>()
+            .add_event::<InsertStationEvent>()
+            .add_event::<UpgradeStationEvent>()
+            .add_event::<UpgradeCatchmentEvent>()
+            .add_event::<RenameStationEvent>()
+            .add_event::<StartBuildingEvent>()
+            .add_event::<MoneyRedrawEvent>()
+            .add_event::<ProlongLineTutorial>()
+            .add_event::<BuildingLineTutorial>();
+        app.add_systems(
+            Update,
+            (build_station, insert_station, close_ring, spawn_station, upgrade_station, upgrade_catchment, rename_station, tick_overcrowding)
+                .chain()
+                .in_set(SimulationSet::Build)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+// ввод игрока и отрисовка станций, без неё станции живут только в симуляции
+pub struct StationPresentationPlugin;
+
+impl Plugin for StationPresentationPlugin {
+    fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
             (
+                attach_station_visuals,
                 hover_select,
                 check_building_position,
                 build_new,
-                spawn_station,
-                build_station,
+                insert_on_segment,
                 debug_draw_passengers,
                 detect_left_release,
-                toggle_warning
+                toggle_warning,
+                mark_interchanges,
             )
                 .run_if(in_state(GameState::InGame)),
         );
     }
 }
 
-#[derive(Component, Clone, Copy, PartialEq)]
+#[derive(Component, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Station {
     pub position: (i32, i32),
 }
@@ -65,23 +159,89 @@ impl Station {
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct StationButton {
+    #[serde(skip)]
     pub selected: bool,
     pub passenger_ids: Vec<usize>,
     pub name: String,
+    #[serde(default)] // индекс в `STATION_CAPACITY_LEVELS`
+    level: usize,
+    #[serde(default)] // индекс в `CATCHMENT_RADII`
+    catchment_level: usize,
+    #[serde(default = "default_built_cost")] // сколько заплачено за саму станцию и пересадочные платформы
+    built_cost: i64,
     gameover_timer: Timer,
 }
 
-impl Default for StationButton {
-    fn default() -> Self {
+// в старых сохранениях цены нет, считаем станцию купленной по обычной цене
+fn default_built_cost() -> i64 {
+    STATION_COST
+}
+
+impl StationButton {
+    pub fn new(name: String, built_cost: i64) -> Self {
         Self {
             selected: false,
             passenger_ids: vec![],
-            name: STATION_NAMES[rand::rng().random_range(0..10)].to_string(),
-            gameover_timer: Timer::new(Duration::from_secs(20), TimerMode::Once),
+            name,
+            level: 0,
+            catchment_level: 0,
+            built_cost,
+            gameover_timer: Timer::new(overcrowding_duration(0), TimerMode::Once),
         }
     }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn capacity(&self) -> usize {
+        STATION_CAPACITY_LEVELS[self.level]
+    }
+
+    pub fn is_overcrowded(&self) -> bool {
+        self.passenger_ids.len() >= self.capacity()
+    }
+
+    // None, если станция уже расширена до предела
+    pub fn upgrade_cost(&self) -> Option<i64> {
+        STATION_UPGRADE_COSTS.get(self.level).copied()
+    }
+
+    pub fn catchment_radius(&self) -> f32 {
+        CATCHMENT_RADII[self.catchment_level]
+    }
+
+    // None, если охват уже максимальный
+    pub fn catchment_upgrade_cost(&self) -> Option<i64> {
+        CATCHMENT_UPGRADE_COSTS.get(self.catchment_level).copied()
+    }
+
+    pub fn built_cost(&self) -> i64 {
+        self.built_cost
+    }
+
+    // к станции провели ещё одну линию
+    pub fn add_platform(&mut self, cost: i64) {
+        self.built_cost += cost;
+    }
+
+    // сколько всего потрачено на расширения, при сносе часть вернётся
+    pub fn invested(&self) -> i64 {
+        STATION_UPGRADE_COSTS[..self.level].iter().sum::<i64>()
+            + CATCHMENT_UPGRADE_COSTS[..self.catchment_level].iter().sum::<i64>()
+    }
+
+    fn upgrade(&mut self) {
+        self.level += 1;
+        self.gameover_timer.set_duration(overcrowding_duration(self.level));
+    }
+}
+
+fn overcrowding_duration(level: usize) -> Duration {
+    let capacity = STATION_CAPACITY_LEVELS[level] as f32 / STATION_CAPACITY_LEVELS[0] as f32;
+    Duration::from_secs_f32(OVERCROWDING_SECS * capacity)
 }
 
 #[derive(Event)]
@@ -99,24 +259,84 @@ pub struct BuildStationEvent {
     pub direction: Direction,
     pub line_to_attach: usize,
 }
+/// Врезать станцию `position` в перегон линии, который начинается на станции `after`.
+#[derive(Event)]
+pub struct InsertStationEvent {
+    pub line_id: usize,
+    pub after: (i32, i32),
+    pub position: (i32, i32),
+}
+
+#[derive(Event)]
+pub struct CloseRingEvent {
+    pub line_id: usize,
+}
+
+/// Переименовать станцию. Имя должно быть непустым и не совпадать с именем другой станции.
+#[derive(Event)]
+pub struct RenameStationEvent {
+    pub position: (i32, i32),
+    pub name: String,
+}
+
+/// Расширить станцию до следующего уровня вместимости.
+#[derive(Event)]
+pub struct UpgradeStationEvent {
+    pub position: (i32, i32),
+}
+
+/// Увеличить радиус, из которого жители приходят на станцию.
+#[derive(Event)]
+pub struct UpgradeCatchmentEvent {
+    pub position: (i32, i32),
+}
+
 #[derive(Event)]
 pub struct SpawnStationEvent {
     pub position: (i32, i32),
-    pub connection: (i32, i32),
+    // сколько за станцию заплатили, столько и учитывается при сносе
+    pub cost: i64,
 }
 
 fn spawn_station(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     mut ev_spawn_station: EventReader<SpawnStationEvent>,
     mut metro: ResMut<Metro>,
+    mut rng: ResMut<GameRng>,
+    q_button: Query<&StationButton>,
 ) {
+    // за кадр может появиться несколько станций, их имена тоже заняты
+    let mut taken: Vec<String> = q_button.iter().map(|button| button.name.clone()).collect();
     for ev in ev_spawn_station.read() {
         let station = Station {
             position: ev.position,
         };
 
+        let button = StationButton::new(unique_name(&STATION_NAMES, &taken, &mut **rng), ev.cost);
+        taken.push(button.name.clone());
+        // println!("name - {}", button.name);
+
+        metro.stations.insert(ev.position, station);
+        commands.spawn((
+            StateScoped(GameState::InGame),
+            Transform::from_translation(Vec3::new(
+                ev.position.0 as f32,
+                ev.position.1 as f32,
+                1.0,
+            )),
+            button,
+            station,
+        ));
+    }
+}
+
+fn attach_station_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    q_station: Query<Entity, Added<Station>>,
+) {
+    for e_station in q_station.iter() {
         let mesh = meshes.add(Circle::new(25.));
         let material = materials.add(Color::BLACK);
 
@@ -129,9 +349,6 @@ fn spawn_station(
             ))
             .id();
 
-        let button = StationButton::default();
-        // println!("name - {}", button.name);
-
         let warning = commands
             .spawn((
                 Text2d::new("!"),
@@ -142,31 +359,52 @@ fn spawn_station(
             ))
             .id();
 
-        metro
-            .stations
-            .add(ev.connection, ev.position, station.clone());
         commands
-            .spawn((
-                StateScoped(GameState::InGame),
-                Mesh2d(mesh),
-                MeshMaterial2d(material),
-                Transform::from_translation(Vec3::new(
-                    ev.position.0 as f32,
-                    ev.position.1 as f32,
-                    1.0,
-                )),
-                button,
-                station,
-            ))
+            .entity(e_station)
+            .insert((Mesh2d(mesh), MeshMaterial2d(material)))
             .add_children(&[inner_circle, warning]);
     }
 }
 
+// кольцо вокруг пересадочной станции
+#[derive(Component)]
+struct InterchangeMarker;
+
+fn mark_interchanges(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    q_station: Query<(Entity, &Station, Option<&Children>)>,
+    q_marker: Query<(), With<InterchangeMarker>>,
+    metro: Res<Metro>,
+) {
+    for (e_station, station, children) in q_station.iter() {
+        let marker = children
+            .into_iter()
+            .flatten()
+            .find(|child| q_marker.contains(**child));
+        match (metro.is_interchange(station.position), marker) {
+            (true, None) => {
+                commands.entity(e_station).with_child((
+                    Mesh2d(meshes.add(Annulus::new(28., 33.))),
+                    MeshMaterial2d(materials.add(Color::BLACK)),
+                    Transform::from_xyz(0., 0., -0.1),
+                    InterchangeMarker,
+                ));
+            }
+            (false, Some(marker)) => commands.entity(*marker).despawn_recursive(),
+            _ => {}
+        }
+    }
+}
+
 fn debug_draw_passengers(q_station: Query<(&Transform, &StationButton)>, mut gizmos: Gizmos) {
     for (transform, station) in q_station.iter() {
+        // у расширенной станции кольцо шире, чтобы больше ожидающих не налезали друг на друга
+        let radius = 40. + 8. * station.level() as f32;
         for i in 0..station.passenger_ids.len() {
             let position = transform.translation.truncate()
-                + 40. * Vec2::from_angle((i as f32) * (2. * PI / (STATION_MAX_PASSENGERS as f32)));
+                + radius * Vec2::from_angle((i as f32) * (2. * PI / (station.capacity() as f32)));
             gizmos.circle_2d(Isometry2d::from_translation(position), 5., Color::BLACK);
         }
     }
@@ -207,33 +445,86 @@ fn build_new(
             return;
         };
 
-        // начинаем строить, определяем, будет это продолжение старой ветки или создание новой
-        for line in metro.lines.iter() {
-            if line.stations.contains(&selected_station) {
-                let mut direction: Direction = Direction::Forwards;
-                let mut line_id = line.id;
-                // if keyboard.pressed(KeyCode::ShiftLeft) {
-                //     line = -1;
-                // }
-
-                if line.stations.front().unwrap() == selected_station {
-                    direction = Direction::Backwards;
-                } else if line.stations.back().unwrap() != selected_station {
-                    println!("Line is not front & isn't back");
-                    line_id = usize::MAX;
-                }
-                ev_start_build.send(StartBuildingEvent {
-                    connection: selected_station.position,
-                    direction,
-                    line_to_attach: line_id,
-                    from_menu: false,
-                });
-                ev_set_blueprint.send(SetBlueprintColorEvent(Color::BLACK.with_alpha(0.5)));
-            }
+        // начинаем строить, определяем, будет это продолжение старой ветки или создание новой.
+        // у пересадочной станции продолжаем ту линию, для которой она конечная
+        let position = selected_station.position;
+        let Some(line) = metro
+            .lines_at(position)
+            .find(|line| line.is_terminal(position))
+            .or(metro.lines_at(position).next())
+        else {
+            return;
+        };
+        let mut direction: Direction = Direction::Forwards;
+        let mut line_id = line.id;
+
+        // со станции посреди линии строится новая линия
+        if !line.is_terminal(position) {
+            line_id = usize::MAX;
+        } else if line.stations.front().unwrap() == selected_station {
+            direction = Direction::Backwards;
         }
+        ev_start_build.send(StartBuildingEvent {
+            connection: position,
+            direction,
+            line_to_attach: line_id,
+            from_menu: false,
+        });
+        ev_set_blueprint.send(SetBlueprintColorEvent(Color::BLACK.with_alpha(0.5)));
     }
 }
 
+// клик по перегону, а не по станции, ставит новую станцию прямо на путь линии
+fn insert_on_segment(
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor_position: Res<CursorPosition>,
+    metro: Res<Metro>,
+    q_station: Query<&StationButton>,
+    q_blueprint: Query<&Visibility, With<StationBlueprint>>,
+    mut ev_insert: EventWriter<InsertStationEvent>,
+) {
+    if !mouse.just_pressed(MouseButton::Left)
+        || q_station.iter().any(|button| button.selected)
+        || q_blueprint.iter().any(|visibility| *visibility != Visibility::Hidden)
+    {
+        return;
+    }
+
+    // у общего перегона нескольких линий берём ближайшую, при равенстве - с меньшим id
+    let Some((line_id, after, point)) = metro
+        .lines
+        .iter()
+        .filter_map(|line| {
+            let (after, point) = line.segment_at(cursor_position.0, SEGMENT_CLICK_RADIUS)?;
+            Some((line.id, after, point))
+        })
+        .min_by(|a, b| {
+            a.2.distance(cursor_position.0)
+                .total_cmp(&b.2.distance(cursor_position.0))
+                .then(a.0.cmp(&b.0))
+        })
+    else {
+        return;
+    };
+    let Some(next) = metro.lines[line_id].next_station(after) else {
+        return;
+    };
+
+    // вплотную к соседям станцию не поставить
+    let too_close = [after, next]
+        .iter()
+        .any(|station| Vec2::new(station.0 as f32, station.1 as f32).distance(point) < JOIN_RADIUS);
+    if too_close {
+        return;
+    }
+
+    ev_insert.send(InsertStationEvent {
+        line_id,
+        after,
+        position: (point.x.round() as i32, point.y.round() as i32),
+    });
+}
+
 fn build_station(
     mut ev_build_station: EventReader<BuildStationEvent>,
     mut metro: ResMut<Metro>,
@@ -245,29 +536,60 @@ fn build_station(
     mut ev_spawn_train: EventWriter<SpawnTrainEvent>,
     mut money: ResMut<Money>,
     mut change_money_ui: EventWriter<MoneyRedrawEvent>,
+    mut rng: ResMut<GameRng>,
+    mut q_station: Query<(&Station, &mut StationButton)>,
 ) {
     for ev in ev_build_station.read() {
-        if money.0 < STATION_COST {
+        // линию провели к уже построенной станции - она становится пересадочной
+        let joins = metro.stations.contains(ev.position);
+        let cost = match joins {
+            true => INTERCHANGE_COST,
+            false => STATION_COST,
+        };
+        // новая линия от станции другой линии делает пересадочной и её
+        let branches = ev.line_to_attach == usize::MAX && metro.lines_at(ev.connection).next().is_some();
+        let branch_cost = if branches { INTERCHANGE_COST } else { 0 };
+        let already_on_line = metro
+            .lines
+            .get(ev.line_to_attach)
+            .is_some_and(|line| line.stations.iter().any(|station| station.position == ev.position));
+        if money.0 < cost + branch_cost || ev.position == ev.connection || already_on_line {
             continue;
         }
 
-        money.0 -= STATION_COST;
+        money.0 -= cost + branch_cost;
         change_money_ui.send(MoneyRedrawEvent);
+        let mut add_platform = |position: (i32, i32), cost: i64| {
+            if let Some((_, mut button)) = q_station.iter_mut().find(|(station, _)| station.position == position) {
+                button.add_platform(cost);
+            }
+        };
+        if joins {
+            add_platform(ev.position, cost);
+        }
+        if branches {
+            add_platform(ev.connection, branch_cost);
+        }
 
         match ev.line_to_attach {
             usize::MAX => {
-                let line = metro.add_line(vec![ev.position, ev.connection]);
+                let line = metro.add_line(vec![ev.position, ev.connection], &mut **rng);
                 ev_spawn_train.send(SpawnTrainEvent {
                     line: line.id,
                     station: ev.position,
+                    model: TrainModel::Standard,
+                    pattern: ServicePattern::AllStops,
+                    cost: 0,
                 });
 
                 ev_spawn_line.send(SpawnLineCurveEvent { line_id: line.id });
 
-                ev_spawn_station.send(SpawnStationEvent {
-                    position: ev.position,
-                    connection: ev.connection,
-                });
+                if !joins {
+                    ev_spawn_station.send(SpawnStationEvent {
+                        position: ev.position,
+                        cost,
+                    });
+                }
                 tutorial_new_line_ev.send(BuildingLineTutorial);
             }
             _ => {
@@ -276,24 +598,80 @@ fn build_station(
                     Direction::Forwards => line.push_back(ev.position),
                     Direction::Backwards => line.push_front(ev.position),
                 }
+                let line_id = line.id;
+                metro.update_line_edges(line_id);
 
                 tutorial_prolong_line_ev.send(ProlongLineTutorial);
 
-                ev_update_line_renderer.send(UpdateLineRendererEvent { line_id: line.id });
+                ev_update_line_renderer.send(UpdateLineRendererEvent { line_id });
 
-                ev_spawn_station.send(SpawnStationEvent {
-                    position: ev.position,
-                    connection: ev.connection,
-                });
+                if !joins {
+                    ev_spawn_station.send(SpawnStationEvent {
+                        position: ev.position,
+                        cost,
+                    });
+                }
             }
         }
     }
 }
 
+// пассажиры, чей маршрут идёт по разрезанному перегону, проезжают и новую станцию
+fn insert_station(
+    mut ev_insert: EventReader<InsertStationEvent>,
+    mut metro: ResMut<Metro>,
+    mut database: ResMut<PassengerDatabase>,
+    mut money: ResMut<Money>,
+    mut change_money_ui: EventWriter<MoneyRedrawEvent>,
+    mut ev_spawn_station: EventWriter<SpawnStationEvent>,
+    mut ev_update_line_renderer: EventWriter<UpdateLineRendererEvent>,
+) {
+    for ev in ev_insert.read() {
+        let Some(next) = metro.lines.get(ev.line_id).and_then(|line| line.next_station(ev.after)) else {
+            continue;
+        };
+        if money.0 < STATION_COST || metro.stations.contains(ev.position) {
+            continue;
+        }
+
+        metro.lines[ev.line_id].insert_after(ev.after, ev.position);
+        metro.update_line_edges(ev.line_id);
+        for passenger in database.0.values_mut() {
+            passenger.insert_stop(ev.line_id, ev.after, next, ev.position);
+        }
+
+        money.0 -= STATION_COST;
+        change_money_ui.send(MoneyRedrawEvent);
+        ev_spawn_station.send(SpawnStationEvent {
+            position: ev.position,
+            cost: STATION_COST,
+        });
+        ev_update_line_renderer.send(UpdateLineRendererEvent { line_id: ev.line_id });
+    }
+}
+
+fn close_ring(
+    mut ev_close_ring: EventReader<CloseRingEvent>,
+    mut metro: ResMut<Metro>,
+    mut ev_update_line_renderer: EventWriter<UpdateLineRendererEvent>,
+) {
+    for ev in ev_close_ring.read() {
+        let line = &mut metro.lines[ev.line_id];
+        if !line.can_close_ring() {
+            continue;
+        }
+
+        line.close_ring();
+        metro.update_line_edges(ev.line_id);
+        ev_update_line_renderer.send(UpdateLineRendererEvent { line_id: ev.line_id });
+    }
+}
+
 fn detect_left_release(
     mouse: Res<ButtonInput<MouseButton>>,
     keyboard: Res<ButtonInput<KeyCode>>,
     mut ev_build_station: EventWriter<BuildStationEvent>,
+    mut ev_close_ring: EventWriter<CloseRingEvent>,
     mut ev_set_blueprint: EventWriter<SetBlueprintColorEvent>,
     mut blueprint_q: Query<(&mut StationBlueprint, &mut Visibility, &Transform)>,
 ) {
@@ -316,11 +694,21 @@ fn detect_left_release(
 
         if keyboard.pressed(KeyCode::ShiftLeft) {
             blueprint.line_to_attach = usize::MAX;
+        } else if blueprint.closes_ring {
+            *vision = Visibility::Hidden;
+            ev_close_ring.send(CloseRingEvent {
+                line_id: blueprint.line_to_attach,
+            });
+            ev_set_blueprint.send(SetBlueprintColorEvent(Color::BLACK.with_alpha(0.0)));
+            return;
         }
 
         *vision = Visibility::Hidden;
+        let position = blueprint
+            .joins
+            .unwrap_or((position.x.round() as i32, position.y.round() as i32));
         ev_build_station.send(BuildStationEvent {
-            position: (position.x.round() as i32, position.y.round() as i32),
+            position,
             connection: blueprint.connection,
             direction: blueprint.direction,
             line_to_attach: blueprint.line_to_attach,
@@ -344,10 +732,6 @@ fn check_building_position(
         panic!("NO BLUEPRINT!");
     };
 
-    if blueprint.line_to_attach == usize::MAX {
-        return;
-    }
-
     let sorted: Vec<(&Transform, &Station)> = q_stations
         .iter()
         .sort_by::<&Transform>(|t1, t2| {
@@ -358,17 +742,29 @@ fn check_building_position(
         .collect();
 
     let (closest_transform, closest_station) = sorted[0];
-
-    if closest_transform
+    let distance = closest_transform
         .translation
-        .distance(cursor_position.0.extend(0.0))
-        <= 100.0
-    {
+        .distance(cursor_position.0.extend(0.0));
+    // новая линия ещё ни с чем не пересекается
+    let line = metro.lines.get(blueprint.line_to_attach);
+    let on_line = line.is_some_and(|line| line.stations.contains(closest_station));
+
+    blueprint.joins = None;
+    blueprint.closes_ring = false;
+    if distance <= JOIN_RADIUS && !on_line && closest_station.position != blueprint.connection {
+        blueprint.can_build = true;
+        blueprint.joins = Some(closest_station.position);
+        ev_set_blueprint.send(SetBlueprintColorEvent(Color::srgba(0.0, 0.4, 1.0, 0.5)));
+    } else if distance <= 100.0 && line.is_some() {
         let color: Color;
-        if metro.lines[blueprint.line_to_attach] //??????????????
-            .stations
-            .contains(&closest_station)
-        {
+        let line = line.unwrap();
+        blueprint.closes_ring = line.can_close_ring()
+            && line.is_terminal(closest_station.position)
+            && closest_station.position != blueprint.connection;
+        if blueprint.closes_ring {
+            blueprint.can_build = true;
+            color = Color::srgba(0.0, 1.0, 0.0, 0.5);
+        } else if on_line {
             blueprint.can_build = false;
             color = Color::srgba(1.0, 0.0, 0.0, 0.5);
         } else {
@@ -383,21 +779,111 @@ fn check_building_position(
     }
 }
 
-fn toggle_warning(
-    mut q_station: Query<(Entity, &mut StationButton)>,
-    mut q_warnings: Query<(&Parent, &mut Visibility, &mut TextColor), With<Text2d>>,
+fn upgrade_station(
+    mut ev_upgrade: EventReader<UpgradeStationEvent>,
+    mut q_station: Query<(&Station, &mut StationButton)>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_upgrade.read() {
+        let Some((_, mut button)) = q_station
+            .iter_mut()
+            .find(|(station, _)| station.position == ev.position)
+        else {
+            continue;
+        };
+        let Some(cost) = button.upgrade_cost() else {
+            continue;
+        };
+        if money.0 < cost {
+            continue;
+        }
+
+        button.upgrade();
+        money.0 -= cost;
+        redraw_money.send(MoneyRedrawEvent);
+    }
+}
+
+fn upgrade_catchment(
+    mut ev_upgrade: EventReader<UpgradeCatchmentEvent>,
+    mut q_station: Query<(&Station, &mut StationButton)>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_upgrade.read() {
+        let Some((_, mut button)) = q_station
+            .iter_mut()
+            .find(|(station, _)| station.position == ev.position)
+        else {
+            continue;
+        };
+        let Some(cost) = button.catchment_upgrade_cost() else {
+            continue;
+        };
+        if money.0 < cost {
+            continue;
+        }
+
+        button.catchment_level += 1;
+        money.0 -= cost;
+        redraw_money.send(MoneyRedrawEvent);
+    }
+}
+
+fn rename_station(
+    mut ev_rename: EventReader<RenameStationEvent>,
+    mut q_station: Query<(&Station, &mut StationButton)>,
+) {
+    for ev in ev_rename.read() {
+        let name = ev.name.trim();
+        if name.is_empty()
+            || q_station
+                .iter()
+                .any(|(station, button)| button.name == name && station.position != ev.position)
+        {
+            continue;
+        }
+
+        if let Some((_, mut button)) = q_station
+            .iter_mut()
+            .find(|(station, _)| station.position == ev.position)
+        {
+            button.name = name.to_string();
+        }
+    }
+}
+
+fn tick_overcrowding(
+    mut q_station: Query<&mut StationButton>,
     time: Res<Time>,
     mut next_state: ResMut<NextState<GameState>>,
-    mut camera_q: Query<&mut Transform, With<MainCamera>>,
 ) {
-    for (station_e, mut station) in q_station.iter_mut() {
+    for mut station in q_station.iter_mut() {
+        if station.is_overcrowded() {
+            station.gameover_timer.tick(time.delta());
+
+            if station.gameover_timer.just_finished() {
+                error!("GAYM OVA");
+                next_state.set(GameState::MainMenu);
+            }
+        } else {
+            station.gameover_timer.reset();
+        }
+    }
+}
+
+fn toggle_warning(
+    q_station: Query<(Entity, &StationButton)>,
+    mut q_warnings: Query<(&Parent, &mut Visibility, &mut TextColor), With<Text2d>>,
+) {
+    for (station_e, station) in q_station.iter() {
         for (_, mut warning, mut color) in q_warnings
             .iter_mut()
             .filter(|(&ref parent, _, _)| parent.get() == station_e)
         {
-            if station.passenger_ids.len() as u32 >= STATION_MAX_PASSENGERS {
+            if station.is_overcrowded() {
                 *warning = Visibility::Visible;
-                station.gameover_timer.tick(time.delta());
 
                 if station.gameover_timer.fraction_remaining() < 0.5 {
                     color.0 = Color::srgb(0.8, 0.8, 0.0);
@@ -406,15 +892,8 @@ fn toggle_warning(
                 if station.gameover_timer.fraction_remaining() < 0.25 {
                     color.0 = Color::srgb(1.0, 0.0, 0.0);
                 }
-
-                if station.gameover_timer.just_finished() {
-                    error!("GAYM OVA");
-                    camera_q.get_single_mut().unwrap().translation = Vec3::new(0., 0., 0.);
-                    next_state.set(GameState::MainMenu);
-                }
             } else {
                 *warning = Visibility::Hidden;
-                station.gameover_timer.reset();
                 color.0 = Color::srgb(0.0, 1.0, 0.0);
             }
         }
@@ -15,6 +15,8 @@ pub struct StationBlueprint {
     pub line_to_attach: usize, //if we want to add new line, send -1
     pub can_build: bool,
     pub menu_flag: bool,
+    pub closes_ring: bool, // отпускаем над другим концом той же линии
+    pub joins: Option<(i32, i32)>, // отпускаем над станцией другой линии - она станет пересадочной
 }
 
 pub struct StationBlueprintPlugin;
@@ -48,6 +50,8 @@ fn init_blueprint(
             line_to_attach: 0,
             can_build: true,
             menu_flag: false,
+            closes_ring: false,
+            joins: None,
         },
         Visibility::Hidden,
     ));
@@ -97,6 +101,8 @@ fn start_building(
             panic!("NO BLUEPRINT");
         };
         blueprint.can_build = true;
+        blueprint.closes_ring = false;
+        blueprint.joins = None;
         blueprint.connection = ev.connection;
         blueprint.direction = ev.direction;
         blueprint.line_to_attach = ev.line_to_attach;
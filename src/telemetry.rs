@@ -0,0 +1,208 @@
+use std::{collections::VecDeque, fs};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::GameClock, line::MetroLine, metro::Metro, simulation::SimulationSet, train::Train,
+    GameState,
+};
+
+pub const TELEMETRY_FILE: &str = "telemetry.ron";
+// сколько последних рейсов и часовых замеров загрузки помнит поезд
+const TRIP_LOG_LEN: usize = 20;
+const LOAD_SAMPLES_LEN: usize = 24;
+
+pub struct TelemetryPlugin;
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportTelemetryEvent>();
+        app.add_systems(
+            Update,
+            (sample_load, export_telemetry)
+                .chain()
+                .in_set(SimulationSet::Economy)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Выгрузить журналы всех поездов в `TELEMETRY_FILE`.
+#[derive(Event)]
+pub struct ExportTelemetryEvent;
+
+/// Сколько раз поезд стоял на станции и сколько там вошло и вышло.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StationTally {
+    pub stops: u32,
+    pub boarded: u32,
+    pub alighted: u32,
+}
+
+/// Рейс от конечной до конечной, у кольца - полный оборот от первой станции.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TripRecord {
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+    pub started_minutes: f64,
+    pub finished_minutes: f64,
+    pub boarded: u32,
+    pub alighted: u32,
+    pub revenue: i64,
+}
+
+/// Журнал работы поезда: по нему видно, окупается ли поезд на своей линии.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TrainTelemetry {
+    pub trips: u32,
+    pub stops: u32,
+    pub revenue: i64,
+    pub stations: HashMap<(i32, i32), StationTally>,
+    // доля занятых мест, замер раз в игровой час
+    pub load_samples: VecDeque<f32>,
+    pub trip_log: VecDeque<TripRecord>,
+    // рейс, который поезд сейчас едет; до первой конечной его нет
+    current_trip: Option<TripRecord>,
+}
+
+impl TrainTelemetry {
+    /// Поезд встал на станции. Если здесь кончается рейс, `trip_end` - игровое время прибытия:
+    /// вышедшие доехали ещё в прошлом рейсе, а вошедшие едут уже в новом.
+    pub fn record_stop(
+        &mut self,
+        station: (i32, i32),
+        alighted: usize,
+        boarded: usize,
+        revenue: i64,
+        trip_end: Option<f64>,
+    ) {
+        self.stops += 1;
+        self.revenue += revenue;
+        let tally = self.stations.entry(station).or_default();
+        tally.stops += 1;
+        tally.alighted += alighted as u32;
+        tally.boarded += boarded as u32;
+
+        if let Some(trip) = &mut self.current_trip {
+            trip.alighted += alighted as u32;
+            trip.revenue += revenue;
+        }
+        if let Some(now) = trip_end {
+            self.finish_trip(station, now);
+        }
+        if let Some(trip) = &mut self.current_trip {
+            trip.boarded += boarded as u32;
+        }
+    }
+
+    // поезд доехал до конечной (или проехал её закрытой): рейс закончен, начинается следующий
+    pub fn finish_trip(&mut self, station: (i32, i32), now: f64) {
+        if let Some(mut trip) = self.current_trip.take() {
+            trip.to = station;
+            trip.finished_minutes = now;
+            self.trips += 1;
+            self.trip_log.push_back(trip);
+            if self.trip_log.len() > TRIP_LOG_LEN {
+                self.trip_log.pop_front();
+            }
+        }
+
+        self.current_trip = Some(TripRecord {
+            from: station,
+            to: station,
+            started_minutes: now,
+            finished_minutes: now,
+            boarded: 0,
+            alighted: 0,
+            revenue: 0,
+        });
+    }
+
+    // рейс обрывается, если поезд переставили на другую линию
+    pub fn abandon_trip(&mut self) {
+        self.current_trip = None;
+    }
+
+    pub fn average_load(&self) -> Option<f32> {
+        if self.load_samples.is_empty() {
+            return None;
+        }
+        Some(self.load_samples.iter().sum::<f32>() / self.load_samples.len() as f32)
+    }
+
+    // станция, где с поезда больше всего сошло и село
+    pub fn busiest_station(&self) -> Option<((i32, i32), StationTally)> {
+        self.stations
+            .iter()
+            .max_by_key(|(position, tally)| (tally.boarded + tally.alighted, **position))
+            .map(|(position, tally)| (*position, *tally))
+    }
+}
+
+// на конечных обычной линии и на первой станции кольца заканчивается рейс
+pub fn ends_trip(line: &MetroLine, station: (i32, i32)) -> bool {
+    line.is_terminal(station)
+        || (line.is_ring && line.stations.front().is_some_and(|first| first.position == station))
+}
+
+fn sample_load(mut q_train: Query<&mut Train>, clock: Res<GameClock>, mut last_hour: Local<Option<u32>>) {
+    if *last_hour == Some(clock.total_hours()) {
+        return;
+    }
+    *last_hour = Some(clock.total_hours());
+
+    for mut train in q_train.iter_mut() {
+        let load = train.passenger_count() as f32 / train.capacity().max(1) as f32;
+        let samples = &mut train.telemetry.load_samples;
+        samples.push_back(load);
+        if samples.len() > LOAD_SAMPLES_LEN {
+            samples.pop_front();
+        }
+    }
+}
+
+// то, что попадает в файл выгрузки по каждому поезду
+#[derive(Serialize)]
+struct TrainReport<'a> {
+    line: &'a str,
+    model: &'a str,
+    cars: usize,
+    telemetry: &'a TrainTelemetry,
+}
+
+fn export_telemetry(
+    mut ev_export: EventReader<ExportTelemetryEvent>,
+    q_train: Query<&Train>,
+    metro: Res<Metro>,
+) {
+    if ev_export.read().count() == 0 {
+        return;
+    }
+
+    let mut trains: Vec<&Train> = q_train.iter().collect();
+    trains.sort_by_key(|train| train.line);
+    let reports: Vec<TrainReport> = trains
+        .into_iter()
+        .map(|train| TrainReport {
+            line: &metro.lines[train.line].name,
+            model: train.model.spec().name,
+            cars: train.cars,
+            telemetry: &train.telemetry,
+        })
+        .collect();
+
+    let serialized = match ron::ser::to_string_pretty(&reports, ron::ser::PrettyConfig::default()) {
+        Ok(serialized) => serialized,
+        Err(e) => {
+            error!("Failed to serialize telemetry: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = fs::write(TELEMETRY_FILE, serialized) {
+        error!("Failed to write {TELEMETRY_FILE}: {e}");
+        return;
+    }
+    info!("Train telemetry exported to {TELEMETRY_FILE}");
+}
@@ -1,289 +1,1041 @@
-use std::time::Duration;
-
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    city_events::OutOfService,
+    clock::GameClock,
+    depot::{InDepot, MAINTENANCE_COST, MAINTENANCE_MINUTES},
+    line::{LinePath, MetroLine, ServicePattern},
     metro::{Direction, Metro},
-    money::Money,
-    passenger::PassengerDatabase,
-    station::{Station, StationButton, STATION_MAX_PASSENGERS},
+    money::{refund, Money, CAR_COST, TRAIN_TRANSFER_COST},
+    passenger::{Journey, PassengerDatabase},
+    routing::alighting_station,
+    signalling::{block_ahead, AtRedSignal, Signals},
+    simulation::SimulationSet,
+    station::{Station, StationButton},
+    telemetry::{ends_trip, TrainTelemetry},
     ui::MoneyRedrawEvent,
     GameState,
 };
 
-const TRAIN_STOP_TIME_SECS: f32 = 1.0;
-const TRAIN_SPEED: f32 = 100.0;
-const TRAIN_MAX_PASSENGERS: usize = 6;
+// стоянка без пассажиров; каждый вышедший и вошедший добавляет ещё немного
+pub const TRAIN_STOP_TIME_SECS: f32 = 0.6;
+const DWELL_SECS_PER_PASSENGER: f32 = 0.15;
+// с такой скоростью поезд подползает к станции в конце торможения, чтобы точно до неё доехать
+const CREEP_SPEED: f32 = 10.;
+// скорость обычного поезда, по ней пассажиры прикидывают время в пути
+pub const TRAIN_SPEED: f32 = 100.0;
+pub const MAX_TRAIN_CARS: usize = 6;
+const CAR_LENGTH: f32 = 18.;
+// поезд догнал впереди идущий, если разрыв меньше этой доли от целевого интервала
+const HOLD_TOLERANCE: f32 = 0.8;
+// дольше этого поезд на станции не держим, иначе плотная линия встанет целиком
+const MAX_HOLD_SECS: f32 = 8.;
+// интервалы, которые можно выбрать для линии; None - поезда расставляются поровну по линии
+pub const HEADWAY_PRESETS: [Option<f32>; 5] = [None, Some(3.), Some(5.), Some(10.), Some(20.)];
+const TRAIN_WIDTH: f32 = 16.;
+
+pub struct TrainModelSpec {
+    pub name: &'static str,
+    // предельная скорость, разгон и торможение
+    pub speed: f32,
+    pub acceleration: f32,
+    pub braking: f32,
+    pub cost: i64,
+    // сколько вагонов у нового поезда и сколько людей влезает в каждый
+    pub cars: usize,
+    pub car_capacity: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub enum TrainModel {
+    #[default]
+    Standard,
+    Express,
+    Heavy,
+}
+
+impl TrainModel {
+    pub const ALL: [TrainModel; 3] = [TrainModel::Standard, TrainModel::Express, TrainModel::Heavy];
+
+    pub fn spec(&self) -> &'static TrainModelSpec {
+        match self {
+            TrainModel::Standard => &TrainModelSpec {
+                name: "Обычный",
+                speed: TRAIN_SPEED,
+                acceleration: 60.,
+                braking: 80.,
+                cost: 50,
+                cars: 2,
+                car_capacity: 3,
+            },
+            // быстрый, но тесный
+            TrainModel::Express => &TrainModelSpec {
+                name: "Экспресс",
+                speed: 160.,
+                acceleration: 80.,
+                braking: 100.,
+                cost: 120,
+                cars: 2,
+                car_capacity: 2,
+            },
+            // медленный, зато сразу длинный
+            TrainModel::Heavy => &TrainModelSpec {
+                name: "Большой",
+                speed: 80.,
+                acceleration: 35.,
+                braking: 50.,
+                cost: 100,
+                cars: 4,
+                car_capacity: 3,
+            },
+        }
+    }
+
+    // следующая модель в списке, по кругу
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|model| model == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // сколько времени съедают торможение перед станцией и разгон после неё по сравнению с проездом
+    pub fn stop_time_loss_secs(&self) -> f32 {
+        let spec = self.spec();
+        spec.speed / (2. * spec.acceleration) + spec.speed / (2. * spec.braking)
+    }
+}
 
 pub struct TrainPlugin;
 
 impl Plugin for TrainPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<SpawnTrainEvent>();
+        app.init_resource::<Headways>();
+        app.add_event::<SpawnTrainEvent>()
+            .add_event::<AddCarEvent>()
+            .add_event::<UpgradeTrainEvent>()
+            .add_event::<SetHeadwayEvent>()
+            .add_event::<SetExpressStopEvent>()
+            .add_event::<SetServicePatternEvent>()
+            .add_event::<TrainOrderEvent>()
+            .add_event::<MoneyRedrawEvent>();
         app.add_systems(
             Update,
             (
                 spawn_train,
+                add_car,
+                upgrade_train,
+                set_headway,
+                set_express_stop,
+                set_service_pattern,
+                give_train_order,
                 move_train,
+                execute_train_orders,
                 stop_train,
                 switch_train_direction,
-                update_train_text,
-            ),
+                measure_headways,
+            )
+                .chain()
+                .in_set(SimulationSet::Trains),
         );
     }
 }
 
+pub struct TrainPresentationPlugin;
+
+impl Plugin for TrainPresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (attach_train_visuals, refresh_train_visuals, update_train_text));
+    }
+}
+
 #[derive(Event)]
 pub struct SpawnTrainEvent {
     pub line: usize,
     pub station: (i32, i32),
+    pub model: TrainModel,
+    pub pattern: ServicePattern,
+    // списывается, только когда поезд появился; первый поезд линии и поезда из сохранения бесплатны
+    pub cost: i64,
 }
 
-#[derive(Component)]
+// приказ поезду выполняется на ближайшей станции, после того как все пассажиры выйдут
+#[derive(Component, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TrainOrder {
+    Sell,
+    // поезд переставляется на первую станцию другой линии
+    Transfer { line: usize },
+    // поезд едет в депо своей линии и встаёт там на обслуживание
+    Maintenance,
+}
+
+#[derive(Event)]
+pub struct TrainOrderEvent {
+    pub train: Entity,
+    pub order: TrainOrder,
+}
+
+#[derive(Event)]
+pub struct SetHeadwayEvent {
+    pub line_id: usize,
+    pub headway_secs: Option<f32>,
+}
+
+// сделать станцию линии экспресс-станцией или снова обычной
+#[derive(Event)]
+pub struct SetExpressStopEvent {
+    pub line_id: usize,
+    pub station: (i32, i32),
+    pub express: bool,
+}
+
+#[derive(Event)]
+pub struct SetServicePatternEvent {
+    pub train: Entity,
+    pub pattern: ServicePattern,
+}
+
+#[derive(Clone, Copy)]
+pub struct Headway {
+    // самый короткий разрыв между соседними поездами линии прямо сейчас
+    pub actual_secs: f32,
+    pub target_secs: f32,
+}
+
+/// Фактический и целевой интервал по линиям, где есть поезда.
+#[derive(Resource, Default)]
+pub struct Headways(pub HashMap<usize, Headway>);
+
+// прицепить вагон к поезду
+#[derive(Event)]
+pub struct AddCarEvent {
+    pub train: Entity,
+}
+
+// заменить модель поезда, докупленные вагоны остаются
+#[derive(Event)]
+pub struct UpgradeTrainEvent {
+    pub train: Entity,
+    pub model: TrainModel,
+}
+
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Train {
-    line: usize,
-    current: usize,
+    pub line: usize,
+    #[serde(default)]
+    pub model: TrainModel,
+    #[serde(default = "standard_cars")]
+    pub cars: usize,
+    #[serde(default)]
+    pub pattern: ServicePattern,
+    #[serde(default)] // износ от 0 до 1, сбрасывается обслуживанием в депо
+    pub wear: f32,
+    #[serde(default)]
+    pub telemetry: TrainTelemetry,
+    #[serde(default)] // сколько проехано от начала пути линии
+    distance: f32,
+    #[serde(default)] // текущая скорость, на станции - ноль
+    speed: f32,
     passenger_ids: Vec<usize>,
     direction: Direction,
-    last_stop_time: Duration,
+    #[serde(skip)] // станция, на которой поезд последний раз стоял или которую проехал
+    last_station: Option<(i32, i32)>,
+    #[serde(skip)] // длина пути, по которому отсчитан `distance`; не совпала - путь перестроили
+    path_length: f32,
+}
+
+// в старых сохранениях все поезда обычные
+fn standard_cars() -> usize {
+    TrainModel::Standard.spec().cars
 }
 
 #[derive(Component)]
 struct TrainStop {
     timer: Timer,
+    // сколько поезд уже простоял сверх обычного, пропуская вперёд соседа
+    held_secs: f32,
 }
 
 impl Train {
-    fn new(line: usize, direction: Direction) -> Self {
+    fn new(
+        line: usize,
+        model: TrainModel,
+        pattern: ServicePattern,
+        direction: Direction,
+        distance: f32,
+        path_length: f32,
+    ) -> Self {
         Self {
             line,
-            current: 0,
+            model,
+            cars: model.spec().cars,
+            pattern,
+            wear: 0.,
+            telemetry: TrainTelemetry::default(),
+            distance,
+            speed: 0.,
             passenger_ids: vec![],
             direction: direction,
-            last_stop_time: Duration::from_millis(0),
+            last_station: None,
+            path_length,
         }
     }
+
+    pub fn passenger_count(&self) -> usize {
+        self.passenger_ids.len()
+    }
+
+    pub fn passenger_ids(&self) -> &[usize] {
+        &self.passenger_ids
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cars * self.model.spec().car_capacity
+    }
+
+    // сколько стоил поезд вместе с докупленными вагонами
+    pub fn value(&self) -> i64 {
+        self.model.spec().cost + (self.cars - self.model.spec().cars) as i64 * CAR_COST
+    }
+
+    // с заменой на `model` докупленные вагоны остаются, но не больше MAX_TRAIN_CARS
+    pub fn cars_after_upgrade(&self, model: TrainModel) -> usize {
+        (model.spec().cars + self.cars - self.model.spec().cars).min(MAX_TRAIN_CARS)
+    }
+
+    // за новую модель платим её цену за вычетом выкупа старой
+    pub fn upgrade_cost(&self, model: TrainModel) -> i64 {
+        (model.spec().cost - refund(self.model.spec().cost)).max(0)
+    }
+
+    // высаживает пассажиров, для которых `condition` вернул true, и возвращает их
+    pub fn unload_where(&mut self, mut condition: impl FnMut(&usize) -> bool) -> Vec<usize> {
+        let (unloaded, kept) = self.passenger_ids.iter().partition(|id| condition(id));
+        self.passenger_ids = kept;
+        unloaded
+    }
 }
 
 fn spawn_train(
     mut commands: Commands,
     mut ev_spawn: EventReader<SpawnTrainEvent>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     metro: Res<Metro>,
+    q_train: Query<&Train>,
+    mut signals: ResMut<Signals>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
 ) {
+    let mut line_trains: Vec<usize> = q_train.iter().map(|train| train.line).collect();
+
     for ev in ev_spawn.read() {
         let line = &metro.lines[ev.line];
 
-        println!("line - {}", ev.line);
-
         if !line.stations.contains(&Station {
             position: ev.station,
         }) {
-            println!("NO TRAIN FOR YOU");
-            return;
+            debug!("station {:?} is not on line {}, no train spawned", ev.station, ev.line);
+            continue;
+        }
+        if money.0 < ev.cost {
+            continue;
         }
 
-        let position = ev.station;
+        // если у линии есть депо, новый поезд выходит из него
+        let position = line.depot.unwrap_or(ev.station);
 
-        let mesh = meshes.add(Rectangle::new(36., 16.));
-        let material = materials.add(line.color);
         let mut direction: Direction = Direction::Forwards;
 
-        if line.stations.back().unwrap().position == position {
-            println!("Got there");
+        if line.is_ring {
+            // по кольцу поезда пускаем по очереди в обе стороны
+            if line_trains.iter().filter(|line_id| **line_id == ev.line).count() % 2 == 1 {
+                direction = Direction::Backwards;
+            }
+            line_trains.push(ev.line);
+        } else if line.stations.back().unwrap().position == position {
             direction = Direction::Backwards;
         }
 
-        commands
+        let Some(path) = &line.path else { continue };
+        let index = line.stations.iter().position(|station| station.position == position).unwrap();
+        let distance = path.station_offsets()[index];
+
+        let e_train = commands
             .spawn((
                 StateScoped(GameState::InGame),
-                Mesh2d(mesh),
-                MeshMaterial2d(material),
                 Transform::from_translation(Vec3::new(position.0 as f32, position.1 as f32, 1.0)),
-                Train::new(ev.line, direction),
+                Train::new(ev.line, ev.model, ev.pattern, direction, distance, path.length()),
             ))
-            .with_child((Text2d::new("0"),));
+            .id();
+
+        if ev.cost > 0 {
+            money.0 -= ev.cost;
+            redraw_money.send(MoneyRedrawEvent);
+        }
+
+        // новый поезд сразу занимает перегон впереди, а если там уже кто-то едет - ждёт на красный
+        match block_ahead(&metro, line, path, distance, direction) {
+            Some(block) if !signals.is_free_for(block, e_train) => {
+                commands.entity(e_train).insert((
+                    TrainStop {
+                        timer: Timer::from_seconds(0., TimerMode::Once),
+                        held_secs: 0.,
+                    },
+                    AtRedSignal,
+                ));
+            }
+            Some(block) => signals.reserve(block, e_train),
+            None => {}
+        }
     }
 }
 
-// код говна
-fn get_closest(positions: &Vec<Vec2>, target: &Vec2, direction: &Direction) -> (Vec2, usize) {
-    let mut sorted = positions.clone();
-
-    sorted.sort_by(|pos1, pos2| pos1.distance(*target).total_cmp(&pos2.distance(*target)));
+fn add_car(
+    mut ev_add_car: EventReader<AddCarEvent>,
+    mut q_train: Query<&mut Train>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_add_car.read() {
+        if money.0 < CAR_COST {
+            continue;
+        }
 
-    match direction {
-        Direction::Forwards => {
-            let index = positions.iter().position(|p| *p == sorted[0]).unwrap();
-            if index + 1 >= positions.len() {
-                return (positions[index], index);
-            }
-            return (positions[index + 1], index + 1);
+        let Ok(mut train) = q_train.get_mut(ev.train) else {
+            continue;
+        };
+        if train.cars >= MAX_TRAIN_CARS {
+            continue;
         }
-        Direction::Backwards => {
-            let index = positions.iter().position(|p| *p == sorted[0]).unwrap();
-            if index <= 0 {
-                return (positions[index], index);
-            }
-            return (positions[index - 1], index - 1);
+
+        train.cars += 1;
+        money.0 -= CAR_COST;
+        redraw_money.send(MoneyRedrawEvent);
+    }
+}
+
+fn upgrade_train(
+    mut ev_upgrade: EventReader<UpgradeTrainEvent>,
+    mut q_train: Query<&mut Train>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_upgrade.read() {
+        let Ok(mut train) = q_train.get_mut(ev.train) else {
+            continue;
+        };
+        let cost = train.upgrade_cost(ev.model);
+        let cars = train.cars_after_upgrade(ev.model);
+        // в тесный поезд те, кто уже едет, могут не поместиться
+        if ev.model == train.model
+            || money.0 < cost
+            || train.passenger_count() > cars * ev.model.spec().car_capacity
+        {
+            continue;
         }
+
+        train.model = ev.model;
+        train.cars = cars;
+        money.0 -= cost;
+        redraw_money.send(MoneyRedrawEvent);
     }
 }
 
-fn offload_passengers(
-    station_button: &mut StationButton,
-    station: &Station,
-    train: &mut Train,
-    passenger_database: &mut ResMut<PassengerDatabase>,
-) -> Vec<usize> {
-    let mut offloading_passengers = vec![];
-    for id in train.passenger_ids.iter() {
-        let passenger = passenger_database.0.get_mut(id).unwrap();
+// длина поезда на экране зависит от числа вагонов, цвет - от линии
+#[derive(Component)]
+struct TrainVisual {
+    cars: usize,
+    line: usize,
+}
+
+fn train_mesh(cars: usize) -> Rectangle {
+    Rectangle::new(CAR_LENGTH * cars as f32, TRAIN_WIDTH)
+}
+
+fn attach_train_visuals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    q_train: Query<(Entity, &Train), Added<Train>>,
+    metro: Res<Metro>,
+) {
+    for (e_train, train) in q_train.iter() {
+        let mesh = meshes.add(train_mesh(train.cars));
+        let material = materials.add(metro.lines[train.line].color);
 
-        if passenger.route.len() <= 0 || passenger.route[0].position == station.position {
-            offloading_passengers.push(*id);
+        commands
+            .entity(e_train)
+            .insert((
+                Mesh2d(mesh),
+                MeshMaterial2d(material),
+                TrainVisual {
+                    cars: train.cars,
+                    line: train.line,
+                },
+            ))
+            .with_child((Text2d::new("0"),));
+    }
+}
+
+fn refresh_train_visuals(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut q_train: Query<(&Train, &mut Mesh2d, &mut MeshMaterial2d<ColorMaterial>, &mut TrainVisual)>,
+    metro: Res<Metro>,
+) {
+    for (train, mut mesh, mut material, mut visual) in q_train.iter_mut() {
+        if visual.cars != train.cars {
+            visual.cars = train.cars;
+            mesh.0 = meshes.add(train_mesh(train.cars));
+        }
+        if visual.line != train.line {
+            visual.line = train.line;
+            material.0 = materials.add(metro.lines[train.line].color);
         }
-        passenger.route.pop();
     }
+}
+
+// ближайшая станция на участке пути от `from` до `to`, кроме той, где поезд уже был.
+// у кольца станции повторяются через каждый круг, поэтому участок может переходить через начало пути
+fn next_station(
+    line: &MetroLine,
+    path: &LinePath,
+    from: f32,
+    to: f32,
+    last_station: Option<(i32, i32)>,
+) -> Option<((i32, i32), f32)> {
+    let laps: &[f32] = match line.is_ring {
+        true => &[-path.length(), 0., path.length()],
+        false => &[0.],
+    };
+    let (low, high) = (from.min(to), from.max(to));
 
-    train.passenger_ids = train
-        .passenger_ids
+    line.stations
         .iter()
-        .filter(|&pass| !offloading_passengers.contains(pass))
-        .map(|pass| pass.clone())
-        .collect();
+        .zip(path.station_offsets())
+        .filter(|(station, _)| Some(station.position) != last_station)
+        .flat_map(|(station, offset)| laps.iter().map(move |lap| (station.position, offset + lap)))
+        .filter(|(_, offset)| (low..=high).contains(offset))
+        .min_by(|(_, a), (_, b)| (a - from).abs().total_cmp(&(b - from).abs()))
+}
 
-    let mut offloaded_passengers = vec![];
-    while station_button.passenger_ids.len() < STATION_MAX_PASSENGERS as usize
-        && offloading_passengers.len() > 0
-    {
-        let offloading_passenger = offloading_passengers.pop().unwrap();
-        offloaded_passengers.push(offloading_passenger);
+// поезд в направлении `direction` довезёт от `from` до `to` без разворота
+fn heading_towards(metro: &Metro, line: &MetroLine, direction: Direction, from: (i32, i32), to: (i32, i32)) -> bool {
+    // по кольцу едем к соседней станции, в которую смотрит поезд
+    if line.is_ring {
+        return metro.next_station(line.id, from, direction) == Some(to);
+    }
+
+    // на конечной поезд всё равно развернётся
+    let direction = match metro.next_station(line.id, from, direction) {
+        Some(_) => direction,
+        None => direction.reversed(),
+    };
+    let mut position = from;
+    while let Some(next) = metro.next_station(line.id, position, direction) {
+        if next == to {
+            return true;
+        }
+        position = next;
     }
+    false
+}
+
+// сколько ехать до ближайшей станции, на которой поезд остановится; к ней он заранее тормозит
+fn distance_to_stop(
+    line: &MetroLine,
+    path: &LinePath,
+    train: &Train,
+    stops_at: impl Fn((i32, i32)) -> bool,
+) -> f32 {
+    let laps: &[f32] = match line.is_ring {
+        true => &[-path.length(), 0., path.length()],
+        false => &[0.],
+    };
+    let sign = match train.direction {
+        Direction::Forwards => 1.,
+        Direction::Backwards => -1.,
+    };
+
+    line.stations
+        .iter()
+        .zip(path.station_offsets())
+        .filter(|(station, _)| Some(station.position) != train.last_station && stops_at(station.position))
+        .flat_map(|(_, offset)| laps.iter().map(move |lap| (offset + lap - train.distance) * sign))
+        .filter(|distance| *distance >= 0.)
+        .fold(f32::INFINITY, f32::min)
+}
 
-    offloaded_passengers
+// чем больше людей вышло и вошло, тем дольше стоянка
+fn dwell_secs(passengers: usize) -> f32 {
+    TRAIN_STOP_TIME_SECS + passengers as f32 * DWELL_SECS_PER_PASSENGER
+}
+
+// высаживает тех, кому выходить здесь; куда они пойдут дальше, решает их состояние поездки
+fn offload_passengers(
+    station: &Station,
+    train: &mut Train,
+    line: &MetroLine,
+    passenger_database: &mut ResMut<PassengerDatabase>,
+) -> usize {
+    let pattern = train.pattern;
+    let offloaded = train.unload_where(|id| {
+        let passenger = passenger_database.0.get_mut(id).unwrap();
+        // схему поезда или экспресс-станции могли поменять, и до своей станции этот поезд уже не довезёт
+        let reaches = alighting_station(&passenger.route)
+            .is_some_and(|position| line.stops_at(pattern, position));
+        passenger.alight(station.position, reaches)
+    });
+    offloaded.len()
 }
 
 fn load_passengers(
     station_button: &mut StationButton,
+    station: &Station,
+    e_train: Entity,
     train: &mut Train,
-    offloaded_passengers: &mut Vec<usize>,
-    pass_database: &ResMut<PassengerDatabase>,
+    pass_database: &mut ResMut<PassengerDatabase>,
     metro: &Res<Metro>,
 ) {
-    while train.passenger_ids.len() < TRAIN_MAX_PASSENGERS && station_button.passenger_ids.len() > 0
-    {
-        let loading_passenger = station_button.passenger_ids[0];
-        let Some(passenger) = pass_database.0.get(&loading_passenger) else {
+    let line = &metro.lines[train.line];
+    for loading_passenger in station_button.passenger_ids.clone() {
+        if train.passenger_ids.len() >= train.capacity() {
+            break;
+        }
+
+        let Some(passenger) = pass_database.0.get_mut(&loading_passenger) else {
+            continue;
+        };
+
+        let (Some(current), Some(next)) = (passenger.route.first(), passenger.route.get(1)) else {
             continue;
         };
 
-        if !metro.lines[train.line]
-            .stations
-            .contains(&passenger.route[0])
+        // садимся только в поезд, который остановится там, где нам выходить
+        if current.station.position != station.position
+            || current.line != train.line
+            || !heading_towards(metro, line, train.direction, station.position, next.station.position)
+            || !alighting_station(&passenger.route).is_some_and(|position| line.stops_at(train.pattern, position))
         {
             continue;
         }
 
-        station_button.passenger_ids.pop();
+        if !passenger.advance(Journey::Riding { train: e_train }) {
+            continue;
+        }
+        station_button
+            .passenger_ids
+            .retain(|id| *id != loading_passenger);
         train.passenger_ids.push(loading_passenger);
     }
+}
+
+// едут только поезда, которые не стоят на станции и не сломаны
+type MovingTrainFilter = (Without<TrainStop>, Without<OutOfService>);
 
-    station_button.passenger_ids.append(offloaded_passengers);
+// всё, что нужно поезду для посадки и высадки на станции
+#[derive(SystemParam)]
+struct Boarding<'w> {
+    money: ResMut<'w, Money>,
+    passenger_database: ResMut<'w, PassengerDatabase>,
+    redraw_money: EventWriter<'w, MoneyRedrawEvent>,
+    clock: Res<'w, GameClock>,
 }
 
 fn move_train(
     mut commands: Commands,
-    mut q_train: Query<(Entity, &mut Transform, &mut Train), Without<TrainStop>>,
-    mut q_station_button: Query<(&mut StationButton, &Station)>,
+    mut q_train: Query<(Entity, &mut Transform, &mut Train, Option<&TrainOrder>), MovingTrainFilter>,
+    mut q_station_button: Query<(&mut StationButton, &Station, Has<OutOfService>)>,
     metro: Res<Metro>,
     time: Res<Time>,
-    mut money: ResMut<Money>,
-    mut passenger_database: ResMut<PassengerDatabase>,
-    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+    mut boarding: Boarding,
+    mut signals: ResMut<Signals>,
 ) {
-    for (e_train, mut train_transform, mut train) in q_train.iter_mut() {
+    // закрытые станции поезда проезжают без остановки
+    let closed: Vec<(i32, i32)> = q_station_button
+        .iter()
+        .filter(|(_, _, is_closed)| *is_closed)
+        .map(|(_, station, _)| station.position)
+        .collect();
+
+    for (e_train, mut train_transform, mut train, order) in q_train.iter_mut() {
         let line = &metro.lines[train.line];
-        let Some(curve) = &line.curve else { return };
-        let curve_positions: Vec<Vec2> =
-            curve.iter_positions(32 * curve.segments().len()).collect();
-
-        // получаем ближайшую точку пути с учётом направления поезда (скорее всего ошибка тут, потому что код говна)
-        let (closest_point, closest_index) = get_closest(
-            &curve_positions,
-            &train_transform.translation.truncate(),
-            &train.direction,
-        );
+        let Some(path) = &line.path else { continue };
 
-        let closest_point_tuple = (
-            closest_point.x.floor() as i32,
-            closest_point.y.floor() as i32,
-        );
+        // линию перестроили или игру загрузили - ставим поезд на ближайшее место нового пути
+        if train.path_length != path.length() {
+            train.distance = path.project(train_transform.translation.truncate());
+            train.path_length = path.length();
+        }
 
-        // проверяем, если текущая точка пути совпадает с позицией станции и нужно сделать остановку
-        if line
-            .stations
-            .iter()
-            .map(|station| station.position)
-            .collect::<Vec<(i32, i32)>>()
-            .contains(&closest_point_tuple)
-            && (time.elapsed() - train.last_stop_time).as_secs_f32() >= TRAIN_STOP_TIME_SECS * 1.1
-        // todo: get rid of magic number
-        {
-            let (mut btn, station) = q_station_button
+        // станции не своей схемы поезд тоже проезжает, а у депо тот, что едет на обслуживание, встаёт всегда
+        let pattern = train.pattern;
+        let maintenance = order == Some(&TrainOrder::Maintenance);
+        let stops_at = |position: (i32, i32)| {
+            !closed.contains(&position)
+                && ((maintenance && line.depot == Some(position)) || line.stops_at(pattern, position))
+        };
+
+        // разгоняемся до предельной скорости, но так, чтобы успеть затормозить к следующей остановке
+        let spec = train.model.spec();
+        let braking_speed = (2. * spec.braking * distance_to_stop(line, path, &train, stops_at)).sqrt();
+        train.speed = (train.speed + spec.acceleration * time.delta_secs())
+            .min(spec.speed)
+            .min(braking_speed.max(CREEP_SPEED));
+
+        let step = match train.direction {
+            Direction::Forwards => train.speed * time.delta_secs(),
+            Direction::Backwards => -train.speed * time.delta_secs(),
+        };
+        let mut from = train.distance;
+        let mut to = match line.is_ring {
+            true => from + step,
+            false => (from + step).clamp(0., path.length()),
+        };
+
+        // останавливаемся на первой станции, которую поезд проезжает за этот кадр
+        while let Some((position, offset)) = next_station(line, path, from, to, train.last_station) {
+            train.last_station = Some(position);
+            signals.release(e_train);
+
+            // проезжаем без остановки, если впереди зелёный
+            if !stops_at(position) {
+                if ends_trip(line, position) {
+                    train.telemetry.finish_trip(position, boarding.clock.total_minutes());
+                }
+                match block_ahead(&metro, line, path, offset, train.direction) {
+                    Some(block) if !signals.is_free_for(block, e_train) => {
+                        to = offset;
+                        train.speed = 0.;
+                        // это не остановка: двери закрыты, и приказы здесь не выполняются
+                        commands.entity(e_train).insert((
+                            TrainStop {
+                                timer: Timer::from_seconds(0., TimerMode::Once),
+                                held_secs: 0.,
+                            },
+                            AtRedSignal,
+                        ));
+                        break;
+                    }
+                    Some(block) => signals.reserve(block, e_train),
+                    None => {}
+                }
+                from = offset;
+                continue;
+            }
+
+            to = offset;
+            train.speed = 0.;
+            let (mut btn, station, _) = q_station_button
                 .iter_mut()
-                .filter(|(_, station)| station.position == closest_point_tuple)
-                .next()
+                .find(|(_, station, _)| station.position == position)
                 .unwrap();
+            let alighted =
+                offload_passengers(station, &mut train, line, &mut boarding.passenger_database);
+            let on_board = train.passenger_count();
 
-            let mut offloaded_passengers =
-                offload_passengers(&mut btn, &station, &mut train, &mut passenger_database);
-
-            money.0 += offloaded_passengers.len() as u32;
-            redraw_money.send(MoneyRedrawEvent);
+            let fare = alighted as i64;
+            boarding.money.0 += fare;
+            boarding.redraw_money.send(MoneyRedrawEvent);
             // println!("денге: {}", money.0);
 
-            load_passengers(
-                &mut btn,
-                &mut train,
-                &mut offloaded_passengers,
-                &passenger_database,
-                &metro,
-            );
-
-            train.last_stop_time = time.elapsed();
+            // поезд с приказом новых пассажиров не берёт
+            if order.is_none() {
+                load_passengers(
+                    &mut btn,
+                    station,
+                    e_train,
+                    &mut train,
+                    &mut boarding.passenger_database,
+                    &metro,
+                );
+            }
+            let boarded = train.passenger_count() - on_board;
+            let trip_end = ends_trip(line, position).then(|| boarding.clock.total_minutes());
+            train
+                .telemetry
+                .record_stop(position, alighted, boarded, fare, trip_end);
 
             commands.entity(e_train).insert(TrainStop {
-                timer: Timer::from_seconds(TRAIN_STOP_TIME_SECS, TimerMode::Once),
+                timer: Timer::from_seconds(dwell_secs(alighted + boarded), TimerMode::Once),
+                held_secs: 0.,
             });
+            break;
         }
 
-        train.current = closest_index;
+        train.distance = path.wrap(to);
 
-        let diff =
-            closest_point.extend(train_transform.translation.z) - train_transform.translation;
-        let angle = diff.y.atan2(diff.x);
+        let tangent = path.tangent(train.distance);
+        let angle = tangent.y.atan2(tangent.x);
         train_transform.rotation = train_transform
             .rotation
             .lerp(Quat::from_rotation_z(angle), 12.0 * time.delta_secs());
+        train_transform.translation = path
+            .position(train.distance)
+            .extend(train_transform.translation.z);
+    }
+}
+
+fn give_train_order(
+    mut commands: Commands,
+    mut ev_order: EventReader<TrainOrderEvent>,
+    q_train: Query<(&Train, Option<&TrainOrder>)>,
+    metro: Res<Metro>,
+    mut money: ResMut<Money>,
+    mut redraw_money: EventWriter<MoneyRedrawEvent>,
+) {
+    for ev in ev_order.read() {
+        let Ok((train, current)) = q_train.get(ev.train) else {
+            continue;
+        };
+        // поездку в депо можно отменить другим приказом, остальные приказы - нет
+        if current.is_some_and(|order| *order != TrainOrder::Maintenance) {
+            continue;
+        }
+
+        if let TrainOrder::Transfer { line } = ev.order {
+            if line == train.line
+                || metro.lines.get(line).is_none_or(|line| line.path.is_none())
+                || money.0 < TRAIN_TRANSFER_COST
+            {
+                continue;
+            }
+
+            money.0 -= TRAIN_TRANSFER_COST;
+            redraw_money.send(MoneyRedrawEvent);
+        }
 
-        let direction = curve_positions[train.current] - train_transform.translation.truncate();
-        train_transform.translation +=
-            direction.normalize().extend(0.) * TRAIN_SPEED * time.delta_secs();
+        commands.entity(ev.train).insert(ev.order);
     }
 }
 
+// поезд с приказом только что встал на станции: высаживаем всех и продаём, переставляем
+// или ставим на обслуживание. перед красным на проезжаемой станции приказ ждёт настоящей остановки
+fn execute_train_orders(
+    mut commands: Commands,
+    mut q_train: Query<
+        (Entity, &mut Train, &mut Transform, &TrainOrder),
+        (Added<TrainStop>, Without<AtRedSignal>),
+    >,
+    metro: Res<Metro>,
+    mut boarding: Boarding,
+) {
+    for (e_train, mut train, mut transform, order) in q_train.iter_mut() {
+        let Some(position) = train.last_station else { continue };
+        // на обслуживание встаём только у депо, до него пассажиров не берём, но довозим
+        if *order == TrainOrder::Maintenance && metro.lines[train.line].depot != Some(position) {
+            continue;
+        }
+
+        // линию, куда переводили поезд, снесли - приказ отменяется, деньги возвращаются
+        if let TrainOrder::Transfer { line } = *order {
+            if metro.lines[line].path.is_none() {
+                boarding.money.0 += TRAIN_TRANSFER_COST;
+                boarding.redraw_money.send(MoneyRedrawEvent);
+                commands.entity(e_train).remove::<TrainOrder>();
+                continue;
+            }
+        }
+
+        // дальше по маршруту пассажиры поедут следующим поездом
+        for id in train.unload_where(|_| true) {
+            boarding.passenger_database.0.get_mut(&id).unwrap().leave_train(position);
+        }
+
+        match *order {
+            TrainOrder::Sell => {
+                boarding.money.0 += refund(train.value());
+                boarding.redraw_money.send(MoneyRedrawEvent);
+                commands.entity(e_train).despawn_recursive();
+                continue;
+            }
+            TrainOrder::Transfer { line } => {
+                let path = metro.lines[line].path.as_ref().unwrap();
+                train.line = line;
+                train.direction = Direction::Forwards;
+                train.distance = 0.;
+                train.speed = 0.;
+                train.path_length = path.length();
+                train.last_station = None;
+                train.telemetry.abandon_trip();
+                transform.translation = path.position(0.).extend(transform.translation.z);
+            }
+            TrainOrder::Maintenance => {
+                train.wear = 0.;
+                boarding.money.0 -= MAINTENANCE_COST;
+                boarding.redraw_money.send(MoneyRedrawEvent);
+                commands.entity(e_train).insert((
+                    InDepot,
+                    OutOfService {
+                        until_minutes: boarding.clock.total_minutes() + MAINTENANCE_MINUTES,
+                    },
+                ));
+            }
+        }
+        commands.entity(e_train).remove::<TrainOrder>();
+    }
+}
+
+// место поезда на круге, который он проезжает: обычная линия туда и обратно, кольцо - один оборот
+fn cycle_position(train: &Train, line: &MetroLine, path: &LinePath) -> (f32, f32) {
+    match (line.is_ring, train.direction) {
+        (true, Direction::Forwards) => (train.distance, path.length()),
+        (true, Direction::Backwards) => (path.length() - train.distance, path.length()),
+        (false, Direction::Forwards) => (train.distance, 2. * path.length()),
+        (false, Direction::Backwards) => (2. * path.length() - train.distance, 2. * path.length()),
+    }
+}
+
+struct Spacing {
+    line: usize,
+    // сколько ехать до впереди идущего поезда и сколько хотелось бы
+    gap_secs: f32,
+    target_secs: f32,
+}
+
+struct StreamTrain {
+    entity: Entity,
+    position: f32,
+    cycle: f32,
+    speed: f32,
+}
+
+// разрыв каждого поезда до следующего за ним по ходу движения.
+// по кольцу поезда в разные стороны идут независимыми потоками
+fn train_spacing(q_train: &Query<(Entity, &Train)>, metro: &Metro) -> HashMap<Entity, Spacing> {
+    let mut streams: HashMap<(usize, bool), Vec<StreamTrain>> = HashMap::new();
+    for (e_train, train) in q_train.iter() {
+        let line = &metro.lines[train.line];
+        let Some(path) = &line.path else { continue };
+        let (position, cycle) = cycle_position(train, line, path);
+        let stream = line.is_ring && train.direction == Direction::Backwards;
+        streams.entry((train.line, stream)).or_default().push(StreamTrain {
+            entity: e_train,
+            position,
+            cycle,
+            speed: train.model.spec().speed,
+        });
+    }
+
+    let mut spacing = HashMap::new();
+    for ((line_id, _), mut trains) in streams {
+        // порядок при равных позициях не должен зависеть от обхода HashMap
+        trains.sort_by(|a, b| a.position.total_cmp(&b.position).then(a.entity.cmp(&b.entity)));
+        let count = trains.len();
+        for (index, train) in trains.iter().enumerate() {
+            // у последнего в списке впереди первый, но уже на следующем круге
+            let gap = match trains.get(index + 1) {
+                Some(next) => next.position - train.position,
+                None => trains[0].position + train.cycle - train.position,
+            };
+            let target_secs = metro.lines[line_id]
+                .headway_secs
+                .unwrap_or(train.cycle / count as f32 / train.speed);
+            spacing.insert(
+                train.entity,
+                Spacing {
+                    line: line_id,
+                    gap_secs: gap / train.speed,
+                    target_secs,
+                },
+            );
+        }
+    }
+    spacing
+}
+
 fn stop_train(
     mut commands: Commands,
-    mut q_train: Query<(Entity, &mut TrainStop)>,
+    // сломанный поезд и поезд в депо со станции не уходят и перегон впереди не занимают
+    mut q_stopped: Query<(Entity, &mut TrainStop, Has<AtRedSignal>), Without<OutOfService>>,
+    q_train: Query<(Entity, &Train)>,
+    metro: Res<Metro>,
     time: Res<Time>,
+    mut signals: ResMut<Signals>,
 ) {
-    for (e_train, mut train_stop) in q_train.iter_mut() {
+    signals.forget_missing(|entity| q_train.contains(entity));
+
+    let mut spacing = None;
+    for (e_train, mut train_stop, at_red_signal) in q_stopped.iter_mut() {
         train_stop.timer.tick(time.delta());
+        if !train_stop.timer.finished() {
+            continue;
+        }
+
+        // догнали впереди идущий поезд - стоим, пока он не уедет подальше
+        let spacing = spacing.get_or_insert_with(|| train_spacing(&q_train, &metro));
+        if let Some(spacing) = spacing.get(&e_train) {
+            if spacing.gap_secs < spacing.target_secs * HOLD_TOLERANCE
+                && train_stop.held_secs < MAX_HOLD_SECS
+            {
+                train_stop.held_secs += time.delta_secs();
+                continue;
+            }
+        }
+
+        // перегон впереди занят - ждём на красный
+        let Ok((_, train)) = q_train.get(e_train) else { continue };
+        let line = &metro.lines[train.line];
+        if let Some(block) = line
+            .path
+            .as_ref()
+            .and_then(|path| block_ahead(&metro, line, path, train.distance, train.direction))
+        {
+            if !signals.is_free_for(block, e_train) {
+                if !at_red_signal {
+                    commands.entity(e_train).insert(AtRedSignal);
+                }
+                continue;
+            }
+            signals.reserve(block, e_train);
+        }
+
+        commands.entity(e_train).remove::<(TrainStop, AtRedSignal)>();
+    }
+}
 
-        if train_stop.timer.just_finished() {
-            commands.entity(e_train).remove::<TrainStop>();
+fn set_headway(mut ev_set_headway: EventReader<SetHeadwayEvent>, mut metro: ResMut<Metro>) {
+    for ev in ev_set_headway.read() {
+        metro.lines[ev.line_id].headway_secs = ev.headway_secs;
+    }
+}
+
+fn set_express_stop(mut ev_set_express: EventReader<SetExpressStopEvent>, mut metro: ResMut<Metro>) {
+    for ev in ev_set_express.read() {
+        let line = &mut metro.lines[ev.line_id];
+        line.express_stops.retain(|stop| *stop != ev.station);
+        if ev.express {
+            line.express_stops.push(ev.station);
+        }
+    }
+}
+
+// новая схема действует со следующей станции; кого поезд не довезёт, выйдут там
+fn set_service_pattern(
+    mut ev_set_pattern: EventReader<SetServicePatternEvent>,
+    mut q_train: Query<&mut Train>,
+) {
+    for ev in ev_set_pattern.read() {
+        if let Ok(mut train) = q_train.get_mut(ev.train) {
+            train.pattern = ev.pattern;
+        }
+    }
+}
+
+fn measure_headways(
+    q_train: Query<(Entity, &Train)>,
+    metro: Res<Metro>,
+    mut headways: ResMut<Headways>,
+) {
+    headways.0.clear();
+    for spacing in train_spacing(&q_train, &metro).values() {
+        let headway = headways.0.entry(spacing.line).or_insert(Headway {
+            actual_secs: spacing.gap_secs,
+            target_secs: spacing.target_secs,
+        });
+        if spacing.gap_secs < headway.actual_secs {
+            *headway = Headway {
+                actual_secs: spacing.gap_secs,
+                target_secs: spacing.target_secs,
+            };
         }
     }
 }
@@ -291,14 +1043,16 @@ fn stop_train(
 fn switch_train_direction(mut q_train: Query<&mut Train>, metro: Res<Metro>) {
     for mut train in q_train.iter_mut() {
         let line = &metro.lines[train.line];
-        let Some(curve) = &line.curve else { return };
-        let curve_positions: Vec<Vec2> =
-            curve.iter_positions(32 * curve.segments().len()).collect();
+        // по кольцу поезда ходят по кругу и не разворачиваются
+        if line.is_ring {
+            continue;
+        }
+        let Some(path) = &line.path else { continue };
 
-        if train.current == 0 && train.direction == Direction::Backwards {
+        if train.distance <= 0. && train.direction == Direction::Backwards {
             train.direction = Direction::Forwards;
         }
-        if train.current == curve_positions.len() - 1 && train.direction == Direction::Forwards {
+        if train.distance >= path.length() && train.direction == Direction::Forwards {
             train.direction = Direction::Backwards;
         }
     }
@@ -0,0 +1,128 @@
+use crate::{clock::GameClock, rng::GameRng, GameState};
+use bevy::prelude::*;
+use bevy_lunex::*;
+
+use super::{UIStyles, UI_FONT};
+
+// в час пик часы подсвечиваются, чтобы игрок ждал наплыва пассажиров
+const RUSH_HOUR_COLOR: Color = Color::srgba(0.8, 0.1, 0.1, 0.95);
+
+pub struct ClockUIPlugin;
+
+impl Plugin for ClockUIPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::InGame), ClockUi::spawn_clock_ui);
+        app.add_systems(Update, (ClockUi::update, ClockUi::update_seed).run_if(in_state(GameState::InGame)));
+    }
+}
+
+#[derive(Component)]
+pub struct ClockUi;
+
+#[derive(Component)]
+pub struct RedrawClock;
+
+// сид партии под часами: с ним ту же игру можно начать заново
+#[derive(Component)]
+struct SeedLabel;
+
+impl ClockUi {
+    fn spawn_clock_ui(mut commands: Commands, asset_server: Res<AssetServer>, clock: Res<GameClock>) {
+        commands
+            .spawn((
+                UiLayoutRoot::new_2d(),
+                StateScoped(GameState::InGame),
+                UiFetchFromCamera::<0>,
+                ClockUi,
+            ))
+            .with_children(|ui| {
+                ui.spawn((
+                    UiLayout::window()
+                        .anchor_left()
+                        .rl_size(20., 5.)
+                        .rl_pos(80., 89.)
+                        .pack(),
+                    Sprite {
+                        image: asset_server.load("button_sliced_top_left.png"),
+                        image_mode: SpriteImageMode::Sliced(TextureSlicer {
+                            border: BorderRect::square(32.0),
+                            ..default()
+                        }),
+                        ..Default::default()
+                    },
+                    UiColor::from(Color::srgba(1., 1., 1., 0.5)),
+                ))
+                .with_children(|ui| {
+                    ui.spawn((
+                        UiLayout::window().anchor_center().pack(),
+                        UiColor::from(Color::BLACK.with_alpha(0.95)),
+                        UiTextSize::from(Rh(100.)),
+                        Text2d::new(clock.to_string()),
+                        TextFont {
+                            font: asset_server.load(UI_FONT),
+                            font_size: 96.,
+                            ..default()
+                        },
+                        TextLayout {
+                            justify: JustifyText::Center,
+                            linebreak: LineBreak::WordBoundary,
+                        },
+                        RedrawClock,
+                    ));
+                });
+                ui.spawn((
+                    UiLayout::window()
+                        .anchor_left()
+                        .rl_size(20., 3.)
+                        .rl_pos(80., 94.)
+                        .pack(),
+                ))
+                .with_children(|ui| {
+                    ui.spawn((
+                        UiLayout::window().anchor_center().pack(),
+                        UiColor::from(Color::BLACK.with_alpha(0.6)),
+                        UiTextSize::from(Rh(80.)),
+                        Text2d::new(""),
+                        TextFont {
+                            font: asset_server.load(UI_FONT),
+                            font_size: 64.,
+                            ..default()
+                        },
+                        SeedLabel,
+                    ));
+                });
+            });
+    }
+
+    // загрузка подменяет генератор уже после появления часов, поэтому сид сверяем каждый кадр
+    fn update_seed(rng: Res<GameRng>, mut q_text: Query<&mut Text2d, With<SeedLabel>>) {
+        let Ok(mut text) = q_text.get_single_mut() else {
+            return;
+        };
+        let label = format!("Сид: {}", rng.seed);
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+
+    fn update(
+        clock: Res<GameClock>,
+        mut redraw_text: Query<(&mut Text2d, &mut UiColor), With<RedrawClock>>,
+    ) {
+        let Ok((mut text, mut color)) = redraw_text.get_single_mut() else {
+            return;
+        };
+
+        // часы тикают каждый кадр, а текст меняем только раз в игровую минуту
+        let time = clock.to_string();
+        if text.0 == time {
+            return;
+        }
+        text.0 = time;
+
+        *color = match clock.is_rush_hour() {
+            true => UiColor::from(RUSH_HOUR_COLOR),
+            false => UiColor::from(Color::BLACK.with_alpha(0.95)),
+        };
+    }
+}
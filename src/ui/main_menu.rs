@@ -1,5 +1,8 @@
-use crate::{audio::ChangeTrackEvent, GameState};
-use bevy::prelude::*;
+use crate::{audio::ChangeTrackEvent, rng::NewGameSeed, save::LoadGameEvent, GameState};
+use bevy::{
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+};
 use bevy_lunex::*;
 
 use super::{TutorialSpawnEvent};
@@ -8,7 +11,8 @@ pub struct MainMenuPlugin;
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SwapStatesEvent>()
-        .add_systems(Update,changing_states_handler.run_if(in_state(GameState::MainMenu)));
+        .init_resource::<SeedField>()
+        .add_systems(Update,(changing_states_handler, type_seed, update_seed_text).run_if(in_state(GameState::MainMenu)));
         app.add_systems(OnEnter(GameState::MainMenu), MainMenuScene::spawn).add_systems(OnExit(GameState::MainMenu), despawn_scene_with::<MainMenuScene>);
     }
 }
@@ -21,15 +25,16 @@ fn despawn_scene_with<S: Component>(mut commands: Commands, query: Query<Entity,
 //2b5797
 pub const METRO_BLUE_COLOR: Color = Color::srgb(0x45 as f32 /255., 0x79 as f32 /255., 0xAE as f32 /255.);
 pub const METRO_LIGHT_BLUE_COLOR: Color = Color::srgb(0x29 as f32 / 255., 0x9b as f32 / 255., 0xe2 as f32 / 255.);
-pub const BUTTON_SIZE: f32 = 14.0;
-pub const BUTTON_GAP: f32 = 11.0;
-pub const MAIN_MENU_BUTTONS: [&str; 4] = ["Новая игра","Обучение","Настройки", "Выйти"];
+pub const BUTTON_SIZE: f32 = 12.0;
+pub const BUTTON_GAP: f32 = 8.0;
+pub const MAIN_MENU_BUTTONS: [&str; 5] = ["Новая игра","Загрузить","Обучение","Настройки", "Выйти"];
 
 pub const UI_FONT: &str = "fonts/FiraSans-Medium.ttf";
 pub const UI_MENU_FONT: &str = "fonts/metromodern.ttf";
 
 pub enum MainMenuStates {
     NewGame = 0,
+    LoadGame,
     Tutorial,
     Settings,
 }
@@ -42,8 +47,21 @@ pub struct SwapStatesEvent{
 #[derive(Component)]
 pub struct MainMenuScene;
 
+// сид новой игры вводится цифрами прямо в меню, пустое поле - случайный город
+#[derive(Resource, Default)]
+struct SeedField {
+    editing: bool,
+}
+
+#[derive(Component)]
+struct SeedText;
+
+// u64 длиннее не бывает
+const MAX_SEED_DIGITS: usize = 19;
+
 impl MainMenuScene {
     fn spawn(mut commands: Commands, asset_server: Res<AssetServer>) {
+        commands.insert_resource(SeedField::default());
         commands
             .spawn((
                 UiLayoutRoot::new_2d(),
@@ -161,6 +179,12 @@ impl MainMenuScene {
                                                 change_music.send(ChangeTrackEvent{track: None});
                                             });
                                         }
+                                        "Загрузить" => {
+                                            button_entity.observe(|_:Trigger<Pointer<Click>>,mut swap_state: EventWriter<SwapStatesEvent>,mut change_music: EventWriter<ChangeTrackEvent>|{
+                                                swap_state.send( SwapStatesEvent { move_to_where: MainMenuStates::LoadGame });
+                                                change_music.send(ChangeTrackEvent{track: None});
+                                            });
+                                        }
                                         "Обучение" => {
                                             button_entity.observe(|_:Trigger<Pointer<Click>>,mut swap_state: EventWriter<SwapStatesEvent>,mut change_music: EventWriter<ChangeTrackEvent>|{
                                                 swap_state.send( SwapStatesEvent { move_to_where: MainMenuStates::Tutorial });       
@@ -186,12 +210,40 @@ impl MainMenuScene {
 
                                 }
                             });
+
+                        // клик по сиду начинает или заканчивает ввод
+                        ui.spawn((
+                            Name::new("Seed"),
+                            UiLayout::window()
+                                .pos(Rl((22.0, 82.0)))
+                                .size(Rl((55.0, 5.0)))
+                                .pack(),
+                            OnHoverSetCursor::new(bevy::window::SystemCursorIcon::Text),
+                        ))
+                        .with_children(|ui| {
+                            ui.spawn((
+                                UiLayout::window().pos((Rh(40.0), Rl(50.0))).anchor(Anchor::CenterLeft).pack(),
+                                UiColor::from(Color::BLACK),
+                                UiTextSize::from(Rh(60.0)),
+                                Text2d::new(""),
+                                TextFont{
+                                    font:asset_server.load(UI_FONT),
+                                    font_size: 64.,
+                                    ..default()
+                                },
+                                PickingBehavior::IGNORE,
+                                SeedText,
+                            ));
+                        })
+                        .observe(|_:Trigger<Pointer<Click>>, mut seed_field: ResMut<SeedField>|{
+                            seed_field.editing = !seed_field.editing;
+                        });
                     });
             });
     }
 }
 
-fn changing_states_handler(mut swap_state_ev: EventReader<SwapStatesEvent>,mut spawn_tutorial_ev: EventWriter<TutorialSpawnEvent>,mut state_manager: ResMut<NextState<GameState>>){
+fn changing_states_handler(mut swap_state_ev: EventReader<SwapStatesEvent>,mut spawn_tutorial_ev: EventWriter<TutorialSpawnEvent>,mut load_game_ev: EventWriter<LoadGameEvent>,mut state_manager: ResMut<NextState<GameState>>){
     for ev in swap_state_ev.read(){
         println!("What?");
         match ev.move_to_where {
@@ -199,6 +251,10 @@ fn changing_states_handler(mut swap_state_ev: EventReader<SwapStatesEvent>,mut s
                 state_manager.set(GameState::InGame);
                 println!("A?");
             }
+            MainMenuStates::LoadGame =>{
+                // в игру переходим только если сохранение удалось прочитать
+                load_game_ev.send(LoadGameEvent);
+            }
             MainMenuStates::Tutorial =>{
                 spawn_tutorial_ev.send(TutorialSpawnEvent);
                 state_manager.set(GameState::InGame);
@@ -212,4 +268,61 @@ fn changing_states_handler(mut swap_state_ev: EventReader<SwapStatesEvent>,mut s
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn type_seed(
+    mut ev_keyboard: EventReader<KeyboardInput>,
+    mut seed_field: ResMut<SeedField>,
+    mut new_game_seed: ResMut<NewGameSeed>,
+) {
+    if !seed_field.editing {
+        ev_keyboard.clear();
+        return;
+    }
+
+    let mut digits = new_game_seed.0.map(|seed| seed.to_string()).unwrap_or_default();
+    for ev in ev_keyboard.read() {
+        if !ev.state.is_pressed() {
+            continue;
+        }
+        match &ev.logical_key {
+            Key::Enter | Key::Escape => seed_field.editing = false,
+            Key::Backspace => {
+                digits.pop();
+            }
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| c.is_ascii_digit()) {
+                    if digits.len() < MAX_SEED_DIGITS {
+                        digits.push(c);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let seed = digits.parse().ok();
+    if new_game_seed.0 != seed {
+        new_game_seed.0 = seed;
+    }
+}
+
+fn update_seed_text(
+    seed_field: Res<SeedField>,
+    new_game_seed: Res<NewGameSeed>,
+    mut q_text: Query<&mut Text2d, With<SeedText>>,
+) {
+    let Ok(mut text) = q_text.get_single_mut() else {
+        return;
+    };
+    let seed = match (new_game_seed.0, seed_field.editing) {
+        (Some(seed), true) => format!("{seed}|"),
+        (None, true) => "|".to_string(),
+        (Some(seed), false) => seed.to_string(),
+        (None, false) => "случайный".to_string(),
+    };
+    let label = format!("Сид: {seed}");
+    if text.0 != label {
+        text.0 = label;
+    }
+}
@@ -10,6 +10,15 @@ pub use tutorial_ui::*;
 mod money_ui;
 pub use money_ui::*;
 
+mod clock_ui;
+pub use clock_ui::*;
+
+mod notification_ui;
+pub use notification_ui::*;
+
+mod train_ui;
+pub use train_ui::*;
+
 mod audio_ui;
 pub use audio_ui::*;
 
@@ -74,15 +74,20 @@ impl MoneyUi {
     fn update(
         mut ev_redraw: EventReader<MoneyRedrawEvent>,
         money_res: Res<Money>,
-        mut redraw_text: Query<&mut Text2d, (With<RedrawMoney>)>,
+        mut redraw_text: Query<(&mut Text2d, &mut UiColor), With<RedrawMoney>>,
     ) {
         for ev in ev_redraw.read() {
-            let Ok(mut text) = redraw_text.get_single_mut() else {
+            let Ok((mut text, mut color)) = redraw_text.get_single_mut() else {
                 println!("WHAT");
                 return;
             };
 
             text.0 = money_res.0.to_string();
+            // в долгах - красным
+            *color = match money_res.0 < 0 {
+                true => UiColor::from(Color::srgba(0.8, 0.1, 0.1, 0.95)),
+                false => UiColor::from(Color::BLACK.with_alpha(0.95)),
+            };
         }
     }
 }
@@ -0,0 +1,115 @@
+use crate::{city_events::CityEventNotification, GameState};
+use bevy::prelude::*;
+use bevy_lunex::*;
+
+use super::{UIStyles, UI_FONT};
+
+// сколько реальных секунд висит сообщение о событии
+const NOTIFICATION_SECS: f32 = 6.;
+
+pub struct NotificationUIPlugin;
+
+impl Plugin for NotificationUIPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CityEventNotification>();
+        app.add_systems(OnEnter(GameState::InGame), NotificationUi::spawn_notification_ui);
+        app.add_systems(
+            Update,
+            (NotificationUi::show, NotificationUi::hide).run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct NotificationUi {
+    timer: Timer,
+}
+
+#[derive(Component)]
+pub struct NotificationText;
+
+impl NotificationUi {
+    fn spawn_notification_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+        commands
+            .spawn((
+                UiLayoutRoot::new_2d(),
+                StateScoped(GameState::InGame),
+                UiFetchFromCamera::<0>,
+            ))
+            .with_children(|ui| {
+                ui.spawn((
+                    UiLayout::window()
+                        .anchor_center()
+                        .rl_size(40., 5.)
+                        .rl_pos(50., 5.)
+                        .pack(),
+                    Sprite {
+                        image: asset_server.load("button_sliced_top_left.png"),
+                        image_mode: SpriteImageMode::Sliced(TextureSlicer {
+                            border: BorderRect::square(32.0),
+                            ..default()
+                        }),
+                        ..Default::default()
+                    },
+                    UiColor::from(Color::srgba(1., 1., 1., 0.8)),
+                    Visibility::Hidden,
+                    NotificationUi {
+                        timer: Timer::from_seconds(NOTIFICATION_SECS, TimerMode::Once),
+                    },
+                ))
+                .with_children(|ui| {
+                    ui.spawn((
+                        UiLayout::window().anchor_center().pack(),
+                        UiColor::from(Color::BLACK.with_alpha(0.95)),
+                        UiTextSize::from(Rh(100.)),
+                        Text2d::new(""),
+                        TextFont {
+                            font: asset_server.load(UI_FONT),
+                            font_size: 96.,
+                            ..default()
+                        },
+                        TextLayout {
+                            justify: JustifyText::Center,
+                            linebreak: LineBreak::WordBoundary,
+                        },
+                        NotificationText,
+                    ));
+                });
+            });
+    }
+
+    // новое событие перекрывает старое сообщение
+    fn show(
+        mut ev_notification: EventReader<CityEventNotification>,
+        mut q_banner: Query<(&mut NotificationUi, &mut Visibility)>,
+        mut q_text: Query<&mut Text2d, With<NotificationText>>,
+    ) {
+        let Some(ev) = ev_notification.read().last() else {
+            return;
+        };
+        let Ok((mut banner, mut visibility)) = q_banner.get_single_mut() else {
+            return;
+        };
+        let Ok(mut text) = q_text.get_single_mut() else {
+            return;
+        };
+
+        text.0 = ev.message.clone();
+        banner.timer.reset();
+        *visibility = Visibility::Visible;
+    }
+
+    fn hide(time: Res<Time>, mut q_banner: Query<(&mut NotificationUi, &mut Visibility)>) {
+        let Ok((mut banner, mut visibility)) = q_banner.get_single_mut() else {
+            return;
+        };
+        if *visibility == Visibility::Hidden {
+            return;
+        }
+
+        banner.timer.tick(time.delta());
+        if banner.timer.finished() {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
@@ -1,20 +1,26 @@
 //redraw lines and text into mb different events?
-use bevy::prelude::*;
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        InputSystem,
+    },
+    prelude::*,
+};
 use bevy_lunex::*;
 //ADD REDRAW EVENT HANDLER, ADD SUPPORT TO NOT RE-CHANGE ALL TEXTs
 use crate::{
-    camera::MainCamera, cursor::CursorPosition, line::MetroLine, metro::{Direction, Metro}, money::{Money, TRAIN_COST}, station::{StartBuildingEvent, Station, StationButton}, station_blueprint::SetBlueprintColorEvent, train::SpawnTrainEvent, ui::MoneyRedrawEvent, GameState
+    camera::MainCamera, cursor::CursorPosition, demolition::{DemolishLineEvent, DemolishStationEvent}, depot::{BuildDepotEvent, DEPOT_COST}, line::{MetroLine, RenameLineEvent, ServicePattern}, metro::{Direction, Metro}, money::Money, station::{RenameStationEvent, StartBuildingEvent, Station, StationButton, UpgradeCatchmentEvent, UpgradeStationEvent}, station_blueprint::SetBlueprintColorEvent, train::{Headways, SetExpressStopEvent, SetHeadwayEvent, SpawnTrainEvent, TrainModel, HEADWAY_PRESETS}, GameState
 };
 
 use super::{BuyTrainTutorial, METRO_LIGHT_BLUE_COLOR, UI_FONT};
 
-pub const RMB_STATS: [&str; 2] = ["Поезда", "Люди на станции"];
-pub const RMB_BUTTONS: [&str; 2] = ["Новая станция", "Новая линия"];
+pub const RMB_STATS: [&str; 6] = ["Поезда", "Люди на станции", "Интервал", "Экспресс-станция", "Схема поезда", "Охват"];
+pub const RMB_BUTTONS: [&str; 4] = ["Новая станция", "Новая линия", "Депо", "Расширить"];
 
 pub const POPUP_WIDTH: f32 = 464.;
 pub const POPUP_HEIGHT: f32 = 192.;
 
-pub const OFFSET_STATS: f32 = 20.;
+pub const OFFSET_STATS: f32 = 100. / RMB_STATS.len() as f32;
 pub const OFFSET_LINES: f32 = 20.;
 pub const LINES_SIZE: f32 = 20.; //shouldn't be greater than offset
 pub const BORDER_WIDTH: f32 = 96.;
@@ -22,8 +28,17 @@ pub const BORDER_WIDTH: f32 = 96.;
 const POPUP_NAME: usize = 0;
 const POPUP_TRAINS_AMOUNT: usize = 1;
 const POPUP_AMOUNT_OF_PEOPLE: usize = 2;
-const POPUP_LINE_HANDLER: usize = 3;
-const POPUP_STATION_BUTTON: usize = 8;
+const POPUP_HEADWAY: usize = 3;
+const POPUP_EXPRESS_STOP: usize = 4;
+const POPUP_PATTERN: usize = 5;
+const POPUP_CATCHMENT: usize = 6;
+const POPUP_LINE_HANDLER: usize = 7;
+const POPUP_STATION_BUTTON: usize = 12;
+const POPUP_DEPOT_BUTTON: usize = 14;
+const POPUP_UPGRADE_BUTTON: usize = 15;
+
+// длиннее имя не влезает в меню
+const MAX_NAME_LENGTH: usize = 24;
 
 pub const OPACITY_LEVEL_MAIN: f32 = 0.8;
 pub const OPACITY_LEVEL_BLUR: f32 = 0.6;
@@ -60,10 +75,17 @@ impl Plugin for StationUIPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<TextboxResource>()
             .init_resource::<LinesResource>()
+            .init_resource::<Renaming>()
             .add_event::<RedrawEvent>()
             .add_event::<ChangeLinesVisibility>()
             .add_event::<RedrawPickedLineEvent>();
         app.add_systems(OnEnter(GameState::InGame), PopupMenu::draw_popup)
+            .add_systems(
+                PreUpdate,
+                type_name
+                    .after(InputSystem)
+                    .run_if(in_state(GameState::InGame)),
+            )
             .add_systems(
                 Update,
                 (
@@ -71,6 +93,10 @@ impl Plugin for StationUIPlugin {
                     draw_menu,
                     redraw_lines_menu,
                     change_visibility_of_lines,
+                    hide_menu_on_demolition,
+                    update_headway_text,
+                    update_service_text,
+                    update_name_text,
                 )
                     .run_if(in_state(GameState::InGame)),
             );
@@ -105,7 +131,23 @@ pub struct TextboxResource {
 pub struct PopupMenu {
     pub station: (i32, i32),
     pub picked_line: usize,
+    pub picked_model: TrainModel,
+    // по какой схеме пойдёт купленный поезд
+    pub picked_pattern: ServicePattern,
 }
+/// Станция или линия, которую игрок переименовывает, и набранное имя.
+#[derive(Resource, Default)]
+pub struct Renaming {
+    target: Option<RenameTarget>,
+    buffer: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RenameTarget {
+    Station((i32, i32)),
+    Line(usize),
+}
+
 #[derive(Resource, Default)]
 pub struct LinesResource {
     pub entities: Vec<Entity>,
@@ -137,6 +179,8 @@ impl PopupMenu {
                 PopupMenu {
                     station: (0, 0),
                     picked_line: 0,
+                    picked_model: TrainModel::Standard,
+                    picked_pattern: ServicePattern::AllStops,
                 },
             ))
             .with_children(|ui| {
@@ -163,6 +207,15 @@ impl PopupMenu {
                             .x(Rl(BORDER_WIDTH / POPUP_WIDTH))
                             .pack(),
                     ))
+                    // клик по названию - переименовать станцию
+                    .observe(|_: Trigger<Pointer<Click>>, popup_q: Query<&PopupMenu, With<UiLayoutRoot>>, station_q: Query<(&Station, &StationButton)>, mut renaming: ResMut<Renaming>| {
+                        let popup = popup_q.get_single().unwrap();
+                        let Some((_, button)) = station_q.iter().find(|(station, _)| station.position == popup.station) else {
+                            return;
+                        };
+                        renaming.target = Some(RenameTarget::Station(popup.station));
+                        renaming.buffer = button.name.clone();
+                    })
                     .with_children(|ui| {
                         popup_textboxes.entities.push(
                             ui.spawn((
@@ -213,7 +266,7 @@ impl PopupMenu {
                                     Name::new(i),
                                     UiLayout::window()
                                         .y(Rl(offset_stats))
-                                        .size(Rl((100., 20.)))
+                                        .size(Rl((100., OFFSET_STATS)))
                                         .pack(),
                                 ))
                                 .with_children(|ui| {
@@ -244,14 +297,14 @@ impl PopupMenu {
                         .with_children(|ui| {
                             let mut offset_stats: f32 = 0.;
                             for i in RMB_STATS {
-                                ui.spawn((
+                                let mut stat_entity = ui.spawn((
                                     Name::new(i),
                                     UiLayout::window()
                                         .y(Rl(offset_stats))
-                                        .size(Rl((100., 20.)))
+                                        .size(Rl((100., OFFSET_STATS)))
                                         .pack(),
-                                ))
-                                .with_children(|ui| {
+                                ));
+                                stat_entity.with_children(|ui| {
                                     popup_textboxes.entities.push(
                                         ui.spawn((
                                             Name::new("Text"),
@@ -268,6 +321,46 @@ impl PopupMenu {
                                         .id(),
                                     );
                                 });
+                                match i {
+                                    // клик по интервалу переключает целевой интервал выбранной линии
+                                    "Интервал" => {
+                                        stat_entity.observe(|_: Trigger<Pointer<Click>>, metro: Res<Metro>, popup_q: Query<&PopupMenu, With<UiLayoutRoot>>, mut set_headway: EventWriter<SetHeadwayEvent>| {
+                                            let popup = popup_q.get_single().unwrap();
+                                            let current = metro.lines[popup.picked_line].headway_secs;
+                                            let index = HEADWAY_PRESETS.iter().position(|preset| *preset == current).unwrap_or(0);
+                                            set_headway.send(SetHeadwayEvent {
+                                                line_id: popup.picked_line,
+                                                headway_secs: HEADWAY_PRESETS[(index + 1) % HEADWAY_PRESETS.len()],
+                                            });
+                                        });
+                                    }
+                                    // экспрессы выбранной линии начнут или перестанут останавливаться на этой станции
+                                    "Экспресс-станция" => {
+                                        stat_entity.observe(|_: Trigger<Pointer<Click>>, metro: Res<Metro>, popup_q: Query<&PopupMenu, With<UiLayoutRoot>>, mut set_express: EventWriter<SetExpressStopEvent>| {
+                                            let popup = popup_q.get_single().unwrap();
+                                            let line = &metro.lines[popup.picked_line];
+                                            set_express.send(SetExpressStopEvent {
+                                                line_id: popup.picked_line,
+                                                station: popup.station,
+                                                express: !line.express_stops.contains(&popup.station),
+                                            });
+                                        });
+                                    }
+                                    "Схема поезда" => {
+                                        stat_entity.observe(|_: Trigger<Pointer<Click>>, mut popup_q: Query<&mut PopupMenu, With<UiLayoutRoot>>| {
+                                            let mut popup = popup_q.get_single_mut().unwrap();
+                                            popup.picked_pattern = popup.picked_pattern.next();
+                                        });
+                                    }
+                                    // клик по охвату расширяет радиус, из которого к станции приходят жители
+                                    "Охват" => {
+                                        stat_entity.observe(|_: Trigger<Pointer<Click>>, popup_q: Query<&PopupMenu, With<UiLayoutRoot>>, mut upgrade_catchment: EventWriter<UpgradeCatchmentEvent>| {
+                                            let popup = popup_q.get_single().unwrap();
+                                            upgrade_catchment.send(UpgradeCatchmentEvent { position: popup.station });
+                                        });
+                                    }
+                                    _ => {}
+                                }
                                 offset_stats += OFFSET_STATS;
                             }
                         });
@@ -337,14 +430,23 @@ impl PopupMenu {
                                         >,
                                          mut redraw_lines_ev: EventWriter<
                                             RedrawPickedLineEvent,
-                                        >| {
+                                        >,
+                                         metro: Res<Metro>,
+                                         mut renaming: ResMut<Renaming>| {
                                             let mut root = ui_root_q.get_single_mut().unwrap();
                                             if !lines_handler_q.get_mut(clck.target).is_ok(){
                                                 return;
                                             }
-                                            root.picked_line = lines_handler_q
+                                            let line_id = lines_handler_q
                                                 .get_mut(clck.target).unwrap()//???? crashes there sometimes
                                                 .line_id;
+                                            // повторный клик по выбранной линии - переименовать её
+                                            if line_id == root.picked_line {
+                                                renaming.target = Some(RenameTarget::Line(line_id));
+                                                renaming.buffer = metro.lines[line_id].name.clone();
+                                                return;
+                                            }
+                                            root.picked_line = line_id;
                                             redraw_lines_ev.send(RedrawPickedLineEvent {
                                                 picked_line_now: root.picked_line,
                                             });
@@ -360,58 +462,35 @@ impl PopupMenu {
                             UiLayout::window().y(Rl(70.)).size(Rl((100., 30.))).pack(),
                         ))
                         .with_children(|ui| {
-                            ui.spawn((
-                                Name::new("Buy Train"),
-                                UiLayout::window().rl_size(100., 35.).rl_pos(0., 0.).pack(),
-                            ))
-                            .with_children(|ui| {
-                                ui.spawn((
-                                    Name::new("New train handler"),
-                                    UiLayout::window()
-                                        .rl_pos(0., 0.)
-                                        .size(Rl((100., 100.)))
-                                        .pack(),
-//                                    Sprite::default(),
-                                    UiHover::new().forward_speed(20.0).backward_speed(4.0),
-//                                    UiColor::from(Color::WHITE.with_alpha(OPACITY_LEVEL_HIGHEST)),
-/*                                    UiColor::new(vec![
-                                        (UiBase::id(), Color::WHITE.with_alpha(0.2)),
-                                        (UiHover::id(), METRO_LIGHT_BLUE_COLOR.with_alpha(0.2)),
-                                    ])*/
-                                ))
-                                .with_children(|ui| {
-                                    ui.spawn((
-                                        Name::new("Buy Train"),
-                                        UiLayout::window().anchor_center().pack(),
-                                        UiHover::new().forward_speed(20.0).backward_speed(4.0),
-                                        UiColor::new(vec![
-                                            (UiBase::id(), Color::WHITE),
-                                            (UiHover::id(), METRO_LIGHT_BLUE_COLOR),
-                                        ]),
-                                        UiTextSize::from(Rh(100.)),
-                                        Text2d::new("Купить поезд"),
-                                        TextFont {
-                                            font: asset_server.load(UI_FONT),
-                                            font_size: 96.,
-                                            ..default()
-                                        },
-                                        PickingBehavior::IGNORE,
-                                    ));
+                            spawn_train_button(ui, &asset_server, &model_label(TrainModel::Standard), 0., 40.)
+                                .observe(|clck: Trigger<Pointer<Click>>, mut popup_q: Query<&mut PopupMenu, With<UiLayoutRoot>>, children_q: Query<&Children>, mut text_q: Query<&mut Text2d>| {
+                                    // каждый клик - следующая модель поезда
+                                    let mut popup = popup_q.get_single_mut().unwrap();
+                                    popup.picked_model = popup.picked_model.next();
+                                    for child in children_q.iter_descendants(clck.entity()) {
+                                        if let Ok(mut text) = text_q.get_mut(child) {
+                                            text.0 = model_label(popup.picked_model);
+                                        }
+                                    }
                                 });
-                            })
-                            .observe(hover_set::<Pointer<Over>, true>)
-                            .observe(hover_set::<Pointer<Out>, false>)
-                            .observe(|_: Trigger<Pointer<Click>>,mut money: ResMut<Money>, metro: Res<Metro>,mut change_money_ui: EventWriter<MoneyRedrawEvent>, mut buy_train: EventWriter<SpawnTrainEvent>, mut buy_train_t: EventWriter<BuyTrainTutorial>,popup_q: Query<&PopupMenu, With<UiLayoutRoot>>| {
+
+                            spawn_train_button(ui, &asset_server, "Купить", 40., 60.)
+                            .observe(|_: Trigger<Pointer<Click>>, money: Res<Money>, mut buy_train: EventWriter<SpawnTrainEvent>, mut buy_train_t: EventWriter<BuyTrainTutorial>,popup_q: Query<&PopupMenu, With<UiLayoutRoot>>| {
                                 let popup = popup_q.get_single().unwrap();
+                                let cost = popup.picked_model.spec().cost;
+                                if money.0 < cost {
+                                    return;
+                                }
+
+                                // деньги спишутся, когда поезд действительно появится на линии
                                 buy_train.send(SpawnTrainEvent{
                                     line: popup.picked_line,
                                     station: popup.station,
+                                    model: popup.picked_model,
+                                    pattern: popup.picked_pattern,
+                                    cost,
                                 });
 
-                                money.0 -= TRAIN_COST;
-                                change_money_ui.send(MoneyRedrawEvent);
-                        
-                                println!("BOUGHT A TRAIN");
                                 buy_train_t.send(BuyTrainTutorial);
                             });
 
@@ -422,7 +501,7 @@ impl PopupMenu {
                                     UiLayout::window()
                                         .y(Rl(35.))
                                         .x(Rl(offset_buttons))
-                                        .size(Rl((50., 65.)))
+                                        .size(Rl((100. / RMB_BUTTONS.len() as f32, 65.)))
                                         .anchor(Anchor::TopLeft)
                                         .pack(),
                                 ));
@@ -452,7 +531,7 @@ impl PopupMenu {
                                                             (UiBase::id(), Color::WHITE),
                                                             (UiHover::id(), METRO_LIGHT_BLUE_COLOR),
                                                         ]),
-                                                        UiTextSize::from(Rh(65.)),
+                                                        UiTextSize::from(Rh(50.)),
                                                         Text2d::new(i),
                                                         TextFont {
                                                             font: asset_server.load(UI_FONT),
@@ -518,7 +597,7 @@ impl PopupMenu {
                                                     (&mut Visibility, &PopupMenu),
                                                     With<UiLayoutRoot>,
                                                 >,
-                                                 mut metro: ResMut<Metro>,
+                                                 metro: Res<Metro>,
                                                  mut ev_change_vision: EventWriter<
                                                     ChangeLinesVisibility,
                                                 >| {
@@ -561,12 +640,39 @@ impl PopupMenu {
                                                 },
                                             );
                                     }
+                                    // депо выбранной линии встаёт у этой станции
+                                    "Депо" => {
+                                        button_entity.observe(
+                                            |_: Trigger<Pointer<Click>>,
+                                             popup_q: Query<&PopupMenu, With<UiLayoutRoot>>,
+                                             mut build_depot: EventWriter<BuildDepotEvent>| {
+                                                let popup = popup_q.get_single().unwrap();
+                                                build_depot.send(BuildDepotEvent {
+                                                    line_id: popup.picked_line,
+                                                    station: popup.station,
+                                                });
+                                            },
+                                        );
+                                    }
+                                    // больше платформ и вестибюль: станция вмещает больше ожидающих
+                                    "Расширить" => {
+                                        button_entity.observe(
+                                            |_: Trigger<Pointer<Click>>,
+                                             popup_q: Query<&PopupMenu, With<UiLayoutRoot>>,
+                                             mut upgrade_station: EventWriter<UpgradeStationEvent>| {
+                                                let popup = popup_q.get_single().unwrap();
+                                                upgrade_station.send(UpgradeStationEvent {
+                                                    position: popup.station,
+                                                });
+                                            },
+                                        );
+                                    }
                                     _ => {
                                         println!("{i}");
                                         panic!("NONAME BUTTON");
                                     }
                                 }
-                                offset_buttons += 50.;
+                                offset_buttons += 100. / RMB_BUTTONS.len() as f32;
                             }
                         });
                     });
@@ -575,6 +681,48 @@ impl PopupMenu {
     }
 }
 
+// название и цена модели поезда для кнопки выбора
+fn model_label(model: TrainModel) -> String {
+    format!("{} ({})", model.spec().name, model.spec().cost)
+}
+
+// кнопка в ряду покупки поездов; `x` и `width` в процентах от ряда
+fn spawn_train_button<'a>(
+    ui: &'a mut ChildBuilder,
+    asset_server: &AssetServer,
+    text: &str,
+    x: f32,
+    width: f32,
+) -> EntityCommands<'a> {
+    let mut button = ui.spawn((
+        Name::new("Train button"),
+        UiLayout::window().rl_size(width, 35.).rl_pos(x, 0.).pack(),
+    ));
+    button
+        .with_children(|ui| {
+            ui.spawn((
+                Name::new("Train button text"),
+                UiLayout::window().anchor_center().pack(),
+                UiHover::new().forward_speed(20.0).backward_speed(4.0),
+                UiColor::new(vec![
+                    (UiBase::id(), Color::WHITE),
+                    (UiHover::id(), METRO_LIGHT_BLUE_COLOR),
+                ]),
+                UiTextSize::from(Rh(100.)),
+                Text2d::new(text),
+                TextFont {
+                    font: asset_server.load(UI_FONT),
+                    font_size: 96.,
+                    ..default()
+                },
+                PickingBehavior::IGNORE,
+            ));
+        })
+        .observe(hover_set::<Pointer<Over>, true>)
+        .observe(hover_set::<Pointer<Out>, false>);
+    button
+}
+
 fn redraw_lines_menu(
     mut redraw_linev_ev: EventReader<RedrawPickedLineEvent>,
     mut button_q: Query<
@@ -583,7 +731,7 @@ fn redraw_lines_menu(
     >,
     mut text_query: Query<&mut UiColor, (With<UiLayout>, Without<LineHandlerFlag>, With<Text2d>)>,
     mut root: Query<&mut PopupMenu, (With<UiLayoutRoot>, Without<LineHandlerFlag>)>,
-    mut metro: ResMut<Metro>,
+    metro: Res<Metro>,
     mut line_handlers_q: Query<
         (&mut LineHandlerFlag, &mut Children),
         Without<Text2d>,
@@ -653,9 +801,7 @@ fn redraw_lines_menu(
 
         let popup_station = metro.find_station(menu.station).unwrap().clone();
 
-        if !(*line.stations.front().unwrap() == popup_station)
-            && !(*line.stations.back().unwrap() == popup_station)
-        {
+        if !line.is_terminal(popup_station.position) {
             *text_query
                 .get_mut(text_references.entities[POPUP_STATION_BUTTON])
                 .unwrap() = UiColor::new(vec![
@@ -713,6 +859,23 @@ fn draw_menu(
         }
     }
 }
+// меню могло остаться открытым у снесённой станции
+fn hide_menu_on_demolition(
+    mut ev_demolish_station: EventReader<DemolishStationEvent>,
+    mut ev_demolish_line: EventReader<DemolishLineEvent>,
+    mut popup_q: Query<&mut Visibility, (With<UiLayoutRoot>, With<PopupMenu>)>,
+    mut ev_change_vision: EventWriter<ChangeLinesVisibility>,
+) {
+    if ev_demolish_station.read().count() + ev_demolish_line.read().count() == 0 {
+        return;
+    }
+
+    for mut popup_visibility in popup_q.iter_mut() {
+        *popup_visibility = Visibility::Hidden;
+    }
+    ev_change_vision.send(ChangeLinesVisibility);
+}
+
 fn redraw_menu(
     mut redraw_popup: EventReader<RedrawEvent>,
     mut text_query: Query<&mut Text2d, (With<UiLayout>, Without<LineHandlerFlag>)>,
@@ -723,7 +886,7 @@ fn redraw_menu(
     text_references: Res<TextboxResource>,
     cursor_pos: Res<CursorPosition>,
     camera_q: Query<&MainCamera>,
-    mut metro: ResMut<Metro>,
+    metro: Res<Metro>,
     mut line_handlers_q: Query<(&mut Visibility, &mut LineHandlerFlag), Without<Text2d>>,
     line_handler_resource: Res<LinesResource>,
     mut redraw_linev_ev: EventWriter<RedrawPickedLineEvent>,
@@ -786,17 +949,12 @@ fn redraw_menu(
             .unwrap()
             .0 = station_info.name.clone();
 
-        text_query
-            .get_mut(text_references.entities[POPUP_AMOUNT_OF_PEOPLE])
-            .unwrap()
-            .0 = station_info.passenger_ids.len().to_string();
-
         text_query
             .get_mut(text_references.entities[POPUP_TRAINS_AMOUNT])
             .unwrap()
             .0 = "1".to_string();
 
-        for i in POPUP_LINE_HANDLER..8 + lines_vec.len() - 5 {
+        for i in POPUP_LINE_HANDLER..POPUP_LINE_HANDLER + lines_vec.len() {
             text_query.get_mut(text_references.entities[i]).unwrap().0 =
                 lines_vec[i - POPUP_LINE_HANDLER].name.clone();
         }
@@ -821,4 +979,199 @@ fn change_visibility_of_lines(
             *lines_q.get_mut(i).unwrap() = Visibility::Hidden;
         }
     }
-}
\ No newline at end of file
+}
+
+// интервал меняется на ходу, поэтому обновляем его, пока меню открыто
+fn update_headway_text(
+    headways: Res<Headways>,
+    metro: Res<Metro>,
+    popup_q: Query<(&PopupMenu, &Visibility), With<UiLayoutRoot>>,
+    text_references: Res<TextboxResource>,
+    mut text_query: Query<&mut Text2d>,
+) {
+    let Ok((popup, visibility)) = popup_q.get_single() else {
+        return;
+    };
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    let Some(line) = metro.lines.get(popup.picked_line) else {
+        return;
+    };
+    let Some(&entity) = text_references.entities.get(POPUP_HEADWAY) else {
+        return;
+    };
+    let Ok(mut text) = text_query.get_mut(entity) else {
+        return;
+    };
+
+    // фактический / целевой, звёздочка - интервал подобран автоматически
+    let label = match headways.0.get(&popup.picked_line) {
+        Some(headway) => format!(
+            "{:.1}/{:.1}с{}",
+            headway.actual_secs,
+            headway.target_secs,
+            if line.headway_secs.is_none() { "*" } else { "" }
+        ),
+        None => "-".to_string(),
+    };
+    if text.0 != label {
+        text.0 = label;
+    }
+}
+
+// экспресс-станция и депо выбранной линии, схема, с которой купится следующий поезд,
+// заполненность станции, её охват и цены расширения
+fn update_service_text(
+    metro: Res<Metro>,
+    station_q: Query<(&Station, &StationButton)>,
+    popup_q: Query<&PopupMenu, With<UiLayoutRoot>>,
+    text_references: Res<TextboxResource>,
+    mut text_query: Query<&mut Text2d>,
+) {
+    let Ok(popup) = popup_q.get_single() else {
+        return;
+    };
+    let Some(line) = metro.lines.get(popup.picked_line) else {
+        return;
+    };
+
+    let express = match line.is_terminal(popup.station) || line.express_stops.contains(&popup.station) {
+        true => "да",
+        false => "нет",
+    };
+    let depot = match line.depot == Some(popup.station) {
+        true => "Депо есть".to_string(),
+        false => format!("Депо ({DEPOT_COST})"),
+    };
+    let Some((_, button)) = station_q.iter().find(|(station, _)| station.position == popup.station) else {
+        return;
+    };
+    let people = format!("{}/{}", button.passenger_ids.len(), button.capacity());
+    let upgrade = match button.upgrade_cost() {
+        Some(cost) => format!("Расширить ({cost})"),
+        None => "Макс. размер".to_string(),
+    };
+    // радиус охвата и цена следующего уровня
+    let catchment = match button.catchment_upgrade_cost() {
+        Some(cost) => format!("{} (+{cost})", button.catchment_radius()),
+        None => format!("{}", button.catchment_radius()),
+    };
+    for (index, label) in [
+        (POPUP_AMOUNT_OF_PEOPLE, people.as_str()),
+        (POPUP_UPGRADE_BUTTON, upgrade.as_str()),
+        (POPUP_EXPRESS_STOP, express),
+        (POPUP_PATTERN, popup.picked_pattern.name()),
+        (POPUP_CATCHMENT, catchment.as_str()),
+        (POPUP_DEPOT_BUTTON, depot.as_str()),
+    ] {
+        let Some(&entity) = text_references.entities.get(index) else {
+            continue;
+        };
+        let Ok(mut text) = text_query.get_mut(entity) else {
+            continue;
+        };
+        if text.0 != label {
+            text.0 = label.to_string();
+        }
+    }
+}
+
+// пока игрок набирает имя, клавиши идут только в поле ввода: камера и горячие клавиши их не видят
+fn type_name(
+    mut ev_keyboard: EventReader<KeyboardInput>,
+    mut keyboard: ResMut<ButtonInput<KeyCode>>,
+    mut renaming: ResMut<Renaming>,
+    popup_q: Query<&Visibility, (With<UiLayoutRoot>, With<PopupMenu>)>,
+    mut ev_rename_station: EventWriter<RenameStationEvent>,
+    mut ev_rename_line: EventWriter<RenameLineEvent>,
+) {
+    let Some(target) = renaming.target else {
+        ev_keyboard.clear();
+        return;
+    };
+    // меню закрыли, не дописав имя
+    if popup_q.get_single().is_ok_and(|visibility| *visibility == Visibility::Hidden) {
+        renaming.target = None;
+        return;
+    }
+
+    for ev in ev_keyboard.read() {
+        if !ev.state.is_pressed() {
+            continue;
+        }
+        match &ev.logical_key {
+            Key::Enter => {
+                let name = renaming.buffer.clone();
+                match target {
+                    RenameTarget::Station(position) => {
+                        ev_rename_station.send(RenameStationEvent { position, name });
+                    }
+                    RenameTarget::Line(line_id) => {
+                        ev_rename_line.send(RenameLineEvent { line_id, name });
+                    }
+                }
+                renaming.target = None;
+                break;
+            }
+            Key::Escape => {
+                renaming.target = None;
+                break;
+            }
+            Key::Backspace => {
+                renaming.buffer.pop();
+            }
+            Key::Space => renaming.buffer.push(' '),
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if renaming.buffer.chars().count() < MAX_NAME_LENGTH {
+                        renaming.buffer.push(c);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    keyboard.reset_all();
+}
+
+// названия станции и её линий; то, что сейчас переименовывают, показывается с курсором
+fn update_name_text(
+    renaming: Res<Renaming>,
+    metro: Res<Metro>,
+    popup_q: Query<&PopupMenu, With<UiLayoutRoot>>,
+    station_q: Query<(&Station, &StationButton)>,
+    line_handlers_q: Query<(&LineHandlerFlag, &Children)>,
+    text_references: Res<TextboxResource>,
+    mut text_query: Query<&mut Text2d>,
+) {
+    let Ok(popup) = popup_q.get_single() else {
+        return;
+    };
+    let label = |target: RenameTarget, name: &str| match renaming.target == Some(target) {
+        true => format!("{}|", renaming.buffer),
+        false => name.to_string(),
+    };
+
+    let mut labels: Vec<(Entity, String)> = vec![];
+    if let Some((_, button)) = station_q.iter().find(|(station, _)| station.position == popup.station) {
+        if let Some(&entity) = text_references.entities.get(POPUP_NAME) {
+            labels.push((entity, label(RenameTarget::Station(popup.station), &button.name)));
+        }
+    }
+    for (handler, children) in line_handlers_q.iter() {
+        let (Some(line), Some(&entity)) = (metro.lines.get(handler.line_id), children.first()) else {
+            continue;
+        };
+        labels.push((entity, label(RenameTarget::Line(line.id), &line.name)));
+    }
+
+    for (entity, label) in labels {
+        let Ok(mut text) = text_query.get_mut(entity) else {
+            continue;
+        };
+        if text.0 != label {
+            text.0 = label;
+        }
+    }
+}
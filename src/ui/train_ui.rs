@@ -0,0 +1,355 @@
+use crate::{
+    cursor::CursorPosition,
+    depot::InDepot,
+    metro::Metro,
+    station::{Station, StationButton},
+    money::{refund, CAR_COST, TRAIN_TRANSFER_COST},
+    telemetry::ExportTelemetryEvent,
+    train::{AddCarEvent, SetServicePatternEvent, Train, TrainOrder, TrainOrderEvent, UpgradeTrainEvent},
+    GameState,
+};
+use bevy::prelude::*;
+use bevy_lunex::*;
+
+use super::{UIStyles, METRO_LIGHT_BLUE_COLOR, UI_FONT};
+
+// как близко к поезду нужно кликнуть, чтобы его выбрать
+const TRAIN_PICK_RADIUS: f32 = 20.;
+// высота строки панели в процентах от панели
+const ROW_HEIGHT: f32 = 100. / 8.;
+
+pub struct TrainUIPlugin;
+
+impl Plugin for TrainUIPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedTrain>();
+        app.add_event::<TrainOrderEvent>()
+            .add_event::<ExportTelemetryEvent>();
+        app.add_systems(OnEnter(GameState::InGame), TrainUi::spawn_train_ui);
+        app.add_systems(
+            Update,
+            (TrainUi::select_train, TrainUi::update).run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Поезд, выбранный правым кликом, и линия, на которую его предлагается перевести.
+#[derive(Resource, Default)]
+pub struct SelectedTrain {
+    pub entity: Option<Entity>,
+    pub target_line: usize,
+}
+
+#[derive(Component)]
+pub struct TrainUi;
+
+// строки панели, которые меняются вместе с выбранным поездом
+#[derive(Component, Clone, Copy)]
+enum TrainPanelText {
+    Info,
+    Stats,
+    LastTrip,
+    Busiest,
+    Pattern,
+    Sell,
+    TargetLine,
+    Upgrade,
+}
+
+impl TrainUi {
+    fn spawn_train_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+        commands
+            .spawn((
+                UiLayoutRoot::new_2d(),
+                StateScoped(GameState::InGame),
+                UiFetchFromCamera::<0>,
+            ))
+            .with_children(|ui| {
+                ui.spawn((
+                    UiLayout::window()
+                        .anchor_left()
+                        .rl_size(30., 40.)
+                        .rl_pos(0., 60.)
+                        .pack(),
+                    Sprite {
+                        image: asset_server.load("button_sliced_top_left.png"),
+                        image_mode: SpriteImageMode::Sliced(TextureSlicer {
+                            border: BorderRect::square(32.0),
+                            ..default()
+                        }),
+                        ..Default::default()
+                    },
+                    UiColor::from(Color::srgba(1., 1., 1., 0.5)),
+                    Visibility::Hidden,
+                    TrainUi,
+                ))
+                .with_children(|ui| {
+                    spawn_panel_text(ui, &asset_server, "", 0., 0., 100., TrainPanelText::Info);
+                    spawn_panel_text(ui, &asset_server, "", 0., 1., 100., TrainPanelText::Stats);
+                    spawn_panel_text(ui, &asset_server, "", 0., 2., 100., TrainPanelText::LastTrip);
+                    spawn_panel_text(ui, &asset_server, "", 0., 3., 100., TrainPanelText::Busiest);
+
+                    // клик перебирает схемы остановок выбранного поезда
+                    spawn_panel_text(ui, &asset_server, "", 0., 4., 100., TrainPanelText::Pattern)
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             selected: Res<SelectedTrain>,
+                             q_train: Query<&Train>,
+                             mut ev_set_pattern: EventWriter<SetServicePatternEvent>| {
+                                let Some(train) = selected.entity else { return };
+                                let Ok(current) = q_train.get(train) else { return };
+                                ev_set_pattern.send(SetServicePatternEvent {
+                                    train,
+                                    pattern: current.pattern.next(),
+                                });
+                            },
+                        );
+
+                    spawn_panel_text(ui, &asset_server, "", 0., 5., 50., TrainPanelText::Sell)
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             mut selected: ResMut<SelectedTrain>,
+                             mut ev_order: EventWriter<TrainOrderEvent>| {
+                                let Some(train) = selected.entity.take() else { return };
+                                ev_order.send(TrainOrderEvent {
+                                    train,
+                                    order: TrainOrder::Sell,
+                                });
+                            },
+                        );
+
+                    spawn_panel_text(
+                        ui,
+                        &asset_server,
+                        &format!("Перевести (-{TRAIN_TRANSFER_COST})"),
+                        50.,
+                        5.,
+                        50.,
+                        (),
+                    )
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             selected: Res<SelectedTrain>,
+                             mut ev_order: EventWriter<TrainOrderEvent>| {
+                                let Some(train) = selected.entity else { return };
+                                ev_order.send(TrainOrderEvent {
+                                    train,
+                                    order: TrainOrder::Transfer {
+                                        line: selected.target_line,
+                                    },
+                                });
+                            },
+                        );
+
+                    // клик перебирает линии, на которые можно перевести поезд
+                    spawn_panel_text(ui, &asset_server, "", 0., 6., 50., TrainPanelText::TargetLine)
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             mut selected: ResMut<SelectedTrain>,
+                             metro: Res<Metro>| {
+                                selected.target_line = next_line(&metro, selected.target_line);
+                            },
+                        );
+
+                    // журналы выгружаются сразу по всем поездам
+                    spawn_panel_text(ui, &asset_server, "Выгрузить журнал", 50., 6., 50., ())
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             mut ev_export: EventWriter<ExportTelemetryEvent>| {
+                                ev_export.send(ExportTelemetryEvent);
+                            },
+                        );
+
+                    spawn_panel_text(ui, &asset_server, &format!("+ Вагон (-{CAR_COST})"), 0., 7., 50., ())
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             selected: Res<SelectedTrain>,
+                             mut ev_add_car: EventWriter<AddCarEvent>| {
+                                let Some(train) = selected.entity else { return };
+                                ev_add_car.send(AddCarEvent { train });
+                            },
+                        );
+
+                    // клик меняет модель выбранного поезда на следующую по списку
+                    spawn_panel_text(ui, &asset_server, "", 50., 7., 50., TrainPanelText::Upgrade)
+                        .observe(hover_set::<Pointer<Over>, true>)
+                        .observe(hover_set::<Pointer<Out>, false>)
+                        .observe(
+                            |_: Trigger<Pointer<Click>>,
+                             selected: Res<SelectedTrain>,
+                             q_train: Query<&Train>,
+                             mut ev_upgrade: EventWriter<UpgradeTrainEvent>| {
+                                let Some(train) = selected.entity else { return };
+                                let Ok(current) = q_train.get(train) else { return };
+                                ev_upgrade.send(UpgradeTrainEvent {
+                                    train,
+                                    model: current.model.next(),
+                                });
+                            },
+                        );
+                });
+            });
+    }
+
+    // правый клик по поезду выбирает его, по пустому месту - снимает выбор
+    fn select_train(
+        mouse: Res<ButtonInput<MouseButton>>,
+        cursor_position: Res<CursorPosition>,
+        q_train: Query<(Entity, &Train, &Transform)>,
+        metro: Res<Metro>,
+        mut selected: ResMut<SelectedTrain>,
+    ) {
+        if !mouse.just_pressed(MouseButton::Right) {
+            return;
+        }
+
+        let picked = q_train.iter().find(|(_, _, transform)| {
+            transform.translation.truncate().distance(cursor_position.0) < TRAIN_PICK_RADIUS
+        });
+        selected.entity = picked.map(|(e_train, _, _)| e_train);
+        if let Some((_, train, _)) = picked {
+            selected.target_line = next_line(&metro, train.line);
+        }
+    }
+
+    fn update(
+        selected: Res<SelectedTrain>,
+        metro: Res<Metro>,
+        q_train: Query<(&Train, Option<&TrainOrder>, Has<InDepot>)>,
+        q_station: Query<(&Station, &StationButton)>,
+        mut q_panel: Query<&mut Visibility, With<TrainUi>>,
+        mut q_text: Query<(&mut Text2d, &TrainPanelText)>,
+    ) {
+        let Ok(mut visibility) = q_panel.get_single_mut() else {
+            return;
+        };
+        // поезд могли продать или снести вместе с линией
+        let Some((train, order, in_depot)) = selected.entity.and_then(|entity| q_train.get(entity).ok()) else {
+            *visibility = Visibility::Hidden;
+            return;
+        };
+        *visibility = Visibility::Visible;
+
+        let mut info = format!(
+            "{}: {}, {} ваг., {}/{}, износ {:.0}%",
+            metro.lines[train.line].name,
+            train.model.spec().name,
+            train.cars,
+            train.passenger_count(),
+            train.capacity(),
+            train.wear * 100.
+        );
+        match order {
+            _ if in_depot => info += " (в депо)",
+            Some(TrainOrder::Maintenance) => info += " (едет в депо)",
+            Some(_) => info += " (до станции)",
+            None => {}
+        }
+
+        let telemetry = &train.telemetry;
+        let load = match telemetry.average_load() {
+            Some(load) => format!("{:.0}%", load * 100.),
+            None => "-".to_string(),
+        };
+        let last_trip = match telemetry.trip_log.back() {
+            Some(trip) => format!(
+                "Прошлый рейс: {} вошло, {} вышло, +{} за {:.0} мин",
+                trip.boarded,
+                trip.alighted,
+                trip.revenue,
+                trip.finished_minutes - trip.started_minutes
+            ),
+            None => "Прошлый рейс: нет".to_string(),
+        };
+        let busiest = telemetry.busiest_station().and_then(|(position, tally)| {
+            let (_, button) = q_station.iter().find(|(station, _)| station.position == position)?;
+            Some(format!(
+                "Больше всего на «{}»: {} вошло, {} вышло",
+                button.name, tally.boarded, tally.alighted
+            ))
+        });
+
+        for (mut text, kind) in q_text.iter_mut() {
+            let label = match kind {
+                TrainPanelText::Info => info.clone(),
+                TrainPanelText::Stats => format!(
+                    "Рейсов {}, остановок {}, выручка {}, загрузка {}",
+                    telemetry.trips, telemetry.stops, telemetry.revenue, load
+                ),
+                TrainPanelText::LastTrip => last_trip.clone(),
+                TrainPanelText::Busiest => busiest.clone().unwrap_or_default(),
+                TrainPanelText::Pattern => format!("Схема: {}", train.pattern.name()),
+                TrainPanelText::Sell => format!("Продать (+{})", refund(train.value())),
+                TrainPanelText::TargetLine => {
+                    format!("На линию: {}", metro.lines[selected.target_line].name)
+                }
+                TrainPanelText::Upgrade => {
+                    let model = train.model.next();
+                    format!("В «{}» (-{})", model.spec().name, train.upgrade_cost(model))
+                }
+            };
+            if text.0 != label {
+                text.0 = label;
+            }
+        }
+    }
+}
+
+// следующая по кругу линия, на которой есть путь
+fn next_line(metro: &Metro, current: usize) -> usize {
+    let count = metro.lines.len();
+    (1..=count)
+        .map(|offset| (current + offset) % count)
+        .find(|line_id| metro.lines[*line_id].path.is_some())
+        .unwrap_or(current)
+}
+
+// строка `row` панели или её часть; `x` и `width` в процентах от панели
+fn spawn_panel_text<'a>(
+    ui: &'a mut ChildBuilder,
+    asset_server: &AssetServer,
+    text: &str,
+    x: f32,
+    row: f32,
+    width: f32,
+    marker: impl Bundle,
+) -> EntityCommands<'a> {
+    let mut row = ui.spawn(
+        UiLayout::window()
+            .rl_size(width, ROW_HEIGHT)
+            .rl_pos(x, row * ROW_HEIGHT)
+            .pack(),
+    );
+    row.with_children(|ui| {
+        ui.spawn((
+            UiLayout::window().anchor_center().pack(),
+            UiHover::new().forward_speed(20.0).backward_speed(4.0),
+            UiColor::new(vec![
+                (UiBase::id(), Color::BLACK.with_alpha(0.95)),
+                (UiHover::id(), METRO_LIGHT_BLUE_COLOR),
+            ]),
+            UiTextSize::from(Rh(70.)),
+            Text2d::new(text),
+            TextFont {
+                font: asset_server.load(UI_FONT),
+                font_size: 96.,
+                ..default()
+            },
+            PickingBehavior::IGNORE,
+            marker,
+        ));
+    });
+    row
+}
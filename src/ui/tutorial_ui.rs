@@ -20,7 +20,6 @@ pub struct TutorialUIPlugin;
 impl Plugin for TutorialUIPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Progress>();
-        app.enable_state_scoped_entities::<GameState>();
         app.add_event::<BuyTrainTutorial>()
             .add_event::<TutorialSpawnEvent>()
             .add_event::<RedrawTextEvent>()
@@ -1,22 +1,62 @@
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap};
 
-pub struct Node<T> {
+pub struct Edge<E> {
+    pub to: (i32, i32),
+    pub weight: f32,
+    pub data: E,
+}
+
+pub struct Node<T, E> {
     id: (i32, i32),
-    pub connections: Vec<(i32, i32)>,
+    pub connections: Vec<Edge<E>>,
     pub data: T
 }
 
-impl<T> Node<T> {
-    fn new(position: (i32, i32), connection: (i32, i32), data: T) -> Self {
-        Self { id: position, connections: vec![connection], data }
+impl<T, E> Node<T, E> {
+    fn new(position: (i32, i32), data: T) -> Self {
+        Self { id: position, connections: vec![], data }
+    }
+
+    pub fn id(&self) -> (i32, i32) {
+        self.id
+    }
+}
+
+/// Кандидат для очереди с приоритетом: `BinaryHeap` - максимальная куча,
+/// а алгоритмам поиска пути нужен самый дешёвый.
+pub struct MinCost<S> {
+    pub cost: f32,
+    pub state: S,
+}
+
+impl<S> PartialEq for MinCost<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl<S> Eq for MinCost<S> {}
+
+impl<S> PartialOrd for MinCost<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S> Ord for MinCost<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
     }
 }
 
-pub struct Graph<T> {
-    pub nodes: HashMap<(i32, i32), Node<T>>
+/// Неориентированный граф с вершинами в точках сетки. Каждое ребро хранится в обеих
+/// вершинах с одинаковыми весом и данными, между двумя вершинами может быть несколько рёбер
+/// (например, перегоны разных линий).
+pub struct Graph<T, E = ()> {
+    pub nodes: HashMap<(i32, i32), Node<T, E>>
 }
 
-impl<T> Default for Graph<T> {
+impl<T, E> Default for Graph<T, E> {
     fn default() -> Self {
         Self {
             nodes: HashMap::new()
@@ -24,29 +64,160 @@ impl<T> Default for Graph<T> {
     }
 }
 
-impl<T> Graph<T> {
-    pub fn add(&mut self, connection: (i32, i32), position: (i32, i32), data: T) {
-        if !self.nodes.contains_key(&connection) {
-            return;
+impl<T, E: Clone> Graph<T, E> {
+    // добавляет вершину без рёбер, существующую не трогает
+    pub fn insert(&mut self, position: (i32, i32), data: T) {
+        self.nodes.entry(position).or_insert_with(|| Node::new(position, data));
+    }
+
+    // удаляет вершину вместе со всеми её рёбрами
+    pub fn remove(&mut self, position: (i32, i32)) -> Option<T> {
+        let node = self.nodes.remove(&position)?;
+
+        for edge in node.connections.iter() {
+            if let Some(neighbour) = self.nodes.get_mut(&edge.to) {
+                neighbour.connections.retain(|back| back.to != position);
+            }
+        }
+
+        Some(node.data)
+    }
+
+    // неориентированное ребро, false если одной из вершин нет или это петля
+    pub fn connect(&mut self, from: (i32, i32), to: (i32, i32), weight: f32, data: E) -> bool {
+        if from == to || !self.nodes.contains_key(&from) || !self.nodes.contains_key(&to) {
+            return false;
+        }
+
+        self.nodes.get_mut(&from).unwrap().connections.push(Edge { to, weight, data: data.clone() });
+        self.nodes.get_mut(&to).unwrap().connections.push(Edge { to: from, weight, data });
+        true
+    }
+
+    /// Оставляет только рёбра, для которых `keep` вернул true. Обе половины ребра
+    /// удаляются вместе, если `keep` не зависит от направления (смотрит на вес и данные).
+    pub fn retain_edges(&mut self, mut keep: impl FnMut(&Edge<E>) -> bool) {
+        for node in self.nodes.values_mut() {
+            node.connections.retain(&mut keep);
+        }
+    }
+
+    pub fn contains(&self, position: (i32, i32)) -> bool {
+        self.nodes.contains_key(&position)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn neighbours(&self, position: (i32, i32)) -> impl Iterator<Item = &Edge<E>> {
+        self.nodes
+            .get(&position)
+            .into_iter()
+            .flat_map(|node| node.connections.iter())
+    }
+
+    // число рёбер вершины, параллельные рёбра считаются по отдельности
+    pub fn degree(&self, position: (i32, i32)) -> usize {
+        self.nodes
+            .get(&position)
+            .map_or(0, |node| node.connections.len())
+    }
+
+    // каждое неориентированное ребро один раз, как (меньшая вершина, ребро к большей)
+    pub fn edges(&self) -> impl Iterator<Item = ((i32, i32), &Edge<E>)> {
+        self.nodes.values().flat_map(|node| {
+            node.connections
+                .iter()
+                .filter(move |edge| node.id() < edge.to)
+                .map(move |edge| (node.id(), edge))
+        })
+    }
+
+    pub fn get(&self, position: (i32, i32)) -> Option<&T> {
+        self.nodes.get(&position).map(|node| &node.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // квадрат 0-1-2-3 с диагональю 0-2 и отдельной вершиной 4
+    fn square() -> Graph<u32, u32> {
+        let mut graph = Graph::default();
+        for (i, position) in [(0, 0), (1, 0), (1, 1), (0, 1), (5, 5)].into_iter().enumerate() {
+            graph.insert(position, i as u32);
         }
+        graph.connect((0, 0), (1, 0), 1., 0);
+        graph.connect((1, 0), (1, 1), 1., 0);
+        graph.connect((1, 1), (0, 1), 1., 1);
+        graph.connect((0, 1), (0, 0), 1., 1);
+        graph.connect((0, 0), (1, 1), 5., 2);
+        graph
+    }
+
+    fn assert_symmetric(graph: &Graph<u32, u32>) {
+        for node in graph.nodes.values() {
+            for edge in node.connections.iter() {
+                let back = graph
+                    .neighbours(edge.to)
+                    .filter(|back| back.to == node.id() && back.data == edge.data && back.weight == edge.weight)
+                    .count();
+                let forth = node
+                    .connections
+                    .iter()
+                    .filter(|other| other.to == edge.to && other.data == edge.data && other.weight == edge.weight)
+                    .count();
+                assert_eq!(back, forth, "{:?} -> {:?}", node.id(), edge.to);
+            }
+        }
+    }
+
+    #[test]
+    fn insert_keeps_existing_node() {
+        let mut graph = square();
+        graph.insert((0, 0), 42);
+        assert_eq!(graph.get((0, 0)), Some(&0));
+        assert_eq!(graph.len(), 5);
+    }
+
+    #[test]
+    fn connect_rejects_missing_nodes_and_loops() {
+        let mut graph = square();
+        assert!(!graph.connect((0, 0), (9, 9), 1., 0));
+        assert!(!graph.connect((0, 0), (0, 0), 1., 0));
+        assert_eq!(graph.degree((0, 0)), 3);
+        assert_symmetric(&graph);
+    }
 
-        self.nodes.insert(position, Node::new(position, connection, data));
-        self.nodes.get_mut(&connection).unwrap().connections.push(position);
+    #[test]
+    fn edges_are_undirected() {
+        let graph = square();
+        assert_symmetric(&graph);
+        assert_eq!(graph.edges().count(), 5);
+        assert_eq!(graph.degree((0, 0)), 3);
+        assert_eq!(graph.degree((5, 5)), 0);
+        assert_eq!(graph.degree((9, 9)), 0);
     }
 
-    pub fn get(&mut self, position: (i32, i32)) -> Option<&T> {
-        if !self.nodes.contains_key(&position) {
-            return None
-        } 
-        
-        Some(&self.nodes.get_mut(&position).unwrap().data)
+    #[test]
+    fn remove_drops_incident_edges() {
+        let mut graph = square();
+        assert_eq!(graph.remove((0, 0)), Some(0));
+        assert_eq!(graph.remove((0, 0)), None);
+        assert!(!graph.contains((0, 0)));
+        assert!(graph.neighbours((1, 0)).all(|edge| edge.to != (0, 0)));
+        assert_eq!(graph.degree((1, 1)), 2);
+        assert_symmetric(&graph);
     }
 
-    pub fn get_mut(&mut self, position: (i32, i32)) -> Option<&mut T> {
-        if !self.nodes.contains_key(&position) {
-            return None
-        } 
-        
-        Some(&mut self.nodes.get_mut(&position).unwrap().data)
+    #[test]
+    fn retain_keeps_both_halves() {
+        let mut graph = square();
+        graph.retain_edges(|edge| edge.data != 1);
+        assert_eq!(graph.edges().count(), 3);
+        assert_eq!(graph.degree((0, 0)), 2);
+        assert_symmetric(&graph);
     }
 }
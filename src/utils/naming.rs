@@ -0,0 +1,57 @@
+use rand::Rng;
+
+/// Случайное имя из `pool`, которого ещё нет среди `taken`. Когда свободные имена
+/// кончились, к имени из пула добавляется номер: «Озерки 2», «Озерки 3».
+pub fn unique_name(pool: &[&str], taken: &[String], rng: &mut impl Rng) -> String {
+    let free: Vec<&str> = pool
+        .iter()
+        .copied()
+        .filter(|name| !taken.iter().any(|other| other == name))
+        .collect();
+    if !free.is_empty() {
+        return free[rng.random_range(0..free.len())].to_string();
+    }
+
+    numbered_name(pool[rng.random_range(0..pool.len())], 2, taken)
+}
+
+/// Первое свободное имя вида «`prefix` N» с номером не меньше `first`.
+pub fn numbered_name(prefix: &str, first: usize, taken: &[String]) -> String {
+    (first..)
+        .map(|number| format!("{prefix} {number}"))
+        .find(|name| !taken.contains(name))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn names_never_repeat() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let pool = ["Озерки", "Парнас", "Автово"];
+        let mut taken: Vec<String> = vec![];
+        for _ in 0..10 {
+            let name = unique_name(&pool, &taken, &mut rng);
+            assert!(!taken.contains(&name), "{name} is already taken");
+            taken.push(name);
+        }
+
+        // сначала разбирается весь пул, потом идут номера
+        for name in pool {
+            assert!(taken.contains(&name.to_string()));
+        }
+        assert!(taken.iter().any(|name| name.ends_with(" 2")));
+    }
+
+    #[test]
+    fn numbered_name_fills_gaps() {
+        let taken = vec!["Линия 1".to_string(), "Линия 3".to_string()];
+        assert_eq!(numbered_name("Линия", 1, &taken), "Линия 2");
+        assert_eq!(numbered_name("Линия", 3, &taken), "Линия 4");
+    }
+}